@@ -1,3 +1,12 @@
+use std::collections::BTreeMap;
+
+use crate::buffer::Buffer;
+use crate::dsp::{db_to_ratio, ratio_to_db, LoudnessMeter, LoudnessReport, Resampler, ResamplerQuality};
+use self::sample::{from_pcm_bytes, from_wav_bytes, full_scale, to_pcm_bytes, to_wav_bytes, Endianness};
+
+/// Conversion of PCM samples between bytes and normalized f64.
+pub mod sample;
+
 const AIFF_SAMPLE_RATE_TABLE : [(usize, [u8;10]); 19] = 
 [
     (8000, [64, 11, 250, 0, 0, 0, 0, 0, 0, 0]),
@@ -53,13 +62,14 @@ impl WavAudioFormat
         }
     } 
 }
+/// Tail of the KSDATAFORMAT SubFormat GUID, following the 16 bits format code.
+const KSDATAFORMAT_SUBTYPE_TAIL : [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71];
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum AIFFAudioFormat
 {
     Uncompressed,
-    Compressed,
-    Error
+    Compressed
 }
 
 /// File format of audio file to open.
@@ -69,68 +79,235 @@ pub enum FileFormat
     None,
     NotLoaded,
     Wav,
-    Aiff
+    Aiff,
+    /// Decoded with the `flac` feature, but cannot be saved.
+    Flac
 }
 impl FileFormat
 {
     fn determine(data : &[u8]) -> Self
     {
-        if let Ok(header) = String::from_utf8(data[0..4].to_vec())
+        match data.get(0..4)
         {
-            if header == "RIFF" { return Self::Wav }
-            else if header == "Form" { return Self::Aiff }
+            Some(b"RIFF" | b"RF64" | b"BW64") => Self::Wav,
+            Some(b"FORM") => Self::Aiff,
+            Some(b"fLaC") => Self::Flac,
+            _ => Self::None
         }
-        eprintln!("ERROR: Failed to determine audio format.");
-        Self::None
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum Endianness
+/// Dither added before reducing bit depth on save.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Dither
 {
-    Big,
-    Little
+    /// Round to the nearest step without dither.
+    #[default]
+    None,
+    /// Uniform noise of one LSB.
+    Rectangular,
+    /// Triangular noise of two LSB, which decorrelates the error from the signal.
+    Triangular,
+    /// Triangular noise from the difference of uniform noise, shifting the noise up in frequency.
+    TriangularHighPass
 }
 
+/// G.711 companding of 8 bits WAV samples.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Companding
+{
+    /// Linear PCM at the bit depth of the file.
+    #[default]
+    None,
+    /// A-law, as used in European telephony.
+    ALaw,
+    /// Mu-law, as used in North American and Japanese telephony.
+    MuLaw
+}
+
+/// Dither noise for each channel while saving.
+struct Ditherer
+{
+    dither : Dither,
+    lsb : f64,
+    state : u32,
+    previous : Vec<f64>
+}
+impl Ditherer
+{
+    fn new(dither : Dither, bit_depth : usize, channels : usize) -> Self
+    {
+        let lsb = 1.0 / full_scale(bit_depth);
+        Self { dither, lsb, state : 0x9E3779B9, previous : vec![0.0; channels] }
+    }
+    // Uniform noise from -0.5 to 0.5 with xorshift.
+    #[inline]
+    fn uniform(&mut self) -> f64
+    {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as f64 / u32::MAX as f64 - 0.5
+    }
+    // Dithered sample, rounded to the nearest step on save.
+    #[inline]
+    fn process(&mut self, channel : usize, sample : f64) -> f64
+    {
+        let noise = match self.dither
+        {
+            Dither::None => return sample,
+            Dither::Rectangular => self.uniform(),
+            Dither::Triangular => self.uniform() + self.uniform(),
+            Dither::TriangularHighPass =>
+            {
+                let noise = self.uniform();
+                let previous = std::mem::replace(&mut self.previous[channel], noise);
+                noise - previous
+            }
+        };
+        sample + noise * self.lsb
+    }
+}
+
+/// Playback direction of a sample loop.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoopType
+{
+    Forward,
+    PingPong,
+    Backward
+}
+impl LoopType
+{
+    fn from_num(num : u32) -> Self
+    {
+        match num { 1 => Self::PingPong, 2 => Self::Backward, _ => Self::Forward }
+    }
+    fn to_num(self) -> u32
+    {
+        match self { Self::Forward => 0, Self::PingPong => 1, Self::Backward => 2 }
+    }
+}
+
+/// Loop of the smpl chunk, with start and end as inclusive sample frames.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SampleLoop
+{
+    pub start : u64,
+    pub end : u64,
+    pub loop_type : LoopType
+}
+
+/// Tags of the LIST/INFO chunk, keyed by the 4 character tag such as "INAM" or "IART".
+pub type InfoTags = BTreeMap<String, String>;
+
+/// Pan law of mixing channels down to mono.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanLaw
+{
+    /// Sum attenuated by 3dB per doubling of channels, keeping the power of uncorrelated channels.
+    Minus3dB,
+    /// Average of the channels, keeping the level of correlated channels.
+    #[default]
+    Minus6dB
+}
+
+///Error while editing audio file.
+#[derive(Debug)]
+pub enum AudioFileError
+{
+    /// Channel counts of the files do not match.
+    ChannelMismatch(usize, usize),
+    /// Sample rates of the files do not match.
+    SampleRateMismatch(usize, usize),
+    /// Lengths of the buffers do not match.
+    LengthMismatch(usize, usize),
+    /// Failed to read or write the file.
+    Io(std::io::Error),
+    /// Format of the file is not recognized, or cannot be written.
+    UnknownFormat,
+    /// Bit depth of the file cannot be read or written.
+    UnsupportedBitDepth(usize),
+    /// File is not valid for its format.
+    Corrupt(String)
+}
+impl std::fmt::Display for AudioFileError
+{
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::ChannelMismatch(this, other) => write!(f, "channel count {} does not match {}", this, other),
+            Self::SampleRateMismatch(this, other) => write!(f, "sample rate {} does not match {}", this, other),
+            Self::LengthMismatch(this, other) => write!(f, "buffer length {} does not match {}", this, other),
+            Self::Io(error) => write!(f, "{}", error),
+            Self::UnknownFormat => write!(f, "unknown audio file format"),
+            Self::UnsupportedBitDepth(bit_depth) => write!(f, "unsupported bit depth of {} bits", bit_depth),
+            Self::Corrupt(reason) => write!(f, "corrupt file : {}", reason)
+        }
+    }
+}
+impl std::error::Error for AudioFileError {}
+impl From<std::io::Error> for AudioFileError { fn from(error : std::io::Error) -> Self { Self::Io(error) } }
+
 pub struct AudioFile
 {
     pub audio_buffer : Vec<Vec<f64>>,
     pub xml_chunk : String,
+    pub midi_unity_note : u32,      // MIDI note played back at the original pitch, from the smpl chunk.
+    pub midi_pitch_fraction : u32,  // Fraction of a semitone above the unity note, from the smpl chunk.
+    pub info_tags : InfoTags,       // Tags of the LIST/INFO chunk of WAV files.
     file_format : FileFormat,
     sample_rate : usize,
-    bit_depth : usize
+    bit_depth : usize,
+    dither : Dither,
+    companding : Companding,
+    loops : Vec<SampleLoop>,
+    channel_mask : Option<u32>
 }
 impl AudioFile
 {
-    /// Load audio file from path.
+    /// Load audio file from path, logging any error.
     pub fn load(&mut self, path : &str)
     {
-        if let Ok(mut file) = std::fs::File::open(path)
-        {
-            let mut buffer = vec![];
-            if let Err(error) = std::io::Read::read(&mut file, &mut buffer) { eprintln!("{}", error); }
-            self.load_bytes(&buffer);
-        }
+        if let Err(error) = self.try_load(path) { eprintln!("ERROR: couldn't load {} : {}", path, error); }
+    }
+    /// Load audio file from path.
+    pub fn try_load(&mut self, path : &str) -> Result<(), AudioFileError>
+    {
+        let buffer = std::fs::read(path)?;
+        self.try_load_bytes(&buffer)
     }
-    /// Save audio file in path.
+    /// Save audio file in path, logging any error.
     pub fn save(&self, path : &str, format : FileFormat)
+    {
+        if let Err(error) = self.try_save(path, format) { eprintln!("ERROR: couldn't save {} : {}", path, error); }
+    }
+    /// Save audio file in path. FLAC files cannot be saved.
+    pub fn try_save(&self, path : &str, format : FileFormat) -> Result<(), AudioFileError>
     {
         match format
         {
             FileFormat::Wav => self.save_wav(path),
             FileFormat::Aiff => self.save_aiff(path),
-            _ => {}
+            _ => Err(AudioFileError::UnknownFormat)
         }
     }
-    /// Load audio file from bytes.
+    /// Load audio file from bytes, logging any error.
     pub fn load_bytes(&mut self, data : &[u8])
+    {
+        if let Err(error) = self.try_load_bytes(data) { eprintln!("ERROR: {}", error); }
+    }
+    /// Load audio file from bytes.
+    pub fn try_load_bytes(&mut self, data : &[u8]) -> Result<(), AudioFileError>
     {
         self.file_format = FileFormat::determine(data);
         match self.file_format
         {
             FileFormat::Wav => self.read_wav(data),
             FileFormat::Aiff => self.read_aiff(data),
-            _ => {}
+            FileFormat::Flac => self.read_flac(data),
+            _ => Err(AudioFileError::UnknownFormat)
         }
     }
     /// Get channel count of the file.
@@ -159,68 +336,262 @@ impl AudioFile
     pub fn set_samples(&mut self, count : usize) { for buffer in &mut self.audio_buffer { buffer.resize(count, 0.0); } }
     /// Set bit depth of the file.
     pub fn set_bit_depth(&mut self, bit_depth : usize) { self.bit_depth = bit_depth; }
+    /// Add a loop, written to the smpl chunk of WAV files.
+    pub fn add_loop(&mut self, sample_loop : SampleLoop) { self.loops.push(sample_loop); }
+    /// Returns the loops of the smpl chunk.
+    pub fn loops(&self) -> &[SampleLoop] { &self.loops }
+    /// Remove every loop.
+    pub fn clear_loops(&mut self) { self.loops.clear(); }
+    /// Returns the INAM tag.
+    pub fn title(&self) -> Option<&str> { self.info_tag("INAM") }
+    /// Set the INAM tag.
+    pub fn set_title(&mut self, title : &str) { self.set_info_tag("INAM", title); }
+    /// Returns the IART tag.
+    pub fn artist(&self) -> Option<&str> { self.info_tag("IART") }
+    /// Set the IART tag.
+    pub fn set_artist(&mut self, artist : &str) { self.set_info_tag("IART", artist); }
+    /// Returns the ICMT tag.
+    pub fn comment(&self) -> Option<&str> { self.info_tag("ICMT") }
+    /// Set the ICMT tag.
+    pub fn set_comment(&mut self, comment : &str) { self.set_info_tag("ICMT", comment); }
+    /// Returns the ICRD tag, the creation date.
+    pub fn date(&self) -> Option<&str> { self.info_tag("ICRD") }
+    /// Set the ICRD tag, the creation date.
+    pub fn set_date(&mut self, date : &str) { self.set_info_tag("ICRD", date); }
+    /// Returns the ISFT tag, the software that created the file.
+    pub fn software(&self) -> Option<&str> { self.info_tag("ISFT") }
+    /// Set the ISFT tag, the software that created the file.
+    pub fn set_software(&mut self, software : &str) { self.set_info_tag("ISFT", software); }
+    /// Returns speaker assignment of WAVE_FORMAT_EXTENSIBLE files, with a bit set per speaker position in channel order.
+    pub fn channel_mask(&self) -> Option<u32> { self.channel_mask }
+    /// Get dither applied on save.
+    pub fn get_dither(&self) -> Dither { self.dither }
+    /// Set dither applied on save.
+    pub fn set_dither(&mut self, dither : Dither) { self.dither = dither; }
+    /// Get companding of WAV files, set when loading an A-law or mu-law file.
+    pub fn get_companding(&self) -> Companding { self.companding }
+    /// Set companding of WAV files, which saves 8 bits A-law or mu-law samples regardless of bit depth.
+    pub fn set_companding(&mut self, companding : Companding) { self.companding = companding; }
     /// Set sample rate of the file.
     pub fn set_sample_rate(&mut self, sample_rate : usize) { self.sample_rate = sample_rate }
-    fn read_wav(&mut self, buffer : &[u8])
+    /// Resample all channels to the sample rate, compensating the filter latency so the length scales with the ratio.
+    pub fn resample(&mut self, sample_rate : usize)
     {
-        if let Ok(header_chunk_id) = String::from_utf8(buffer[0..4].to_vec())
+        if sample_rate == self.sample_rate || sample_rate == 0 { return }
+        let len = (self.num_sample() as u128 * sample_rate as u128).div_ceil(self.sample_rate.max(1) as u128) as usize;
+        for channel in self.audio_buffer.iter_mut()
         {
-            if header_chunk_id != "RIFF"
-            {
-                eprintln!("ERROR: Wrong header chunk id.");
-                return
-            }
+            let mut resampler = Resampler::new(self.sample_rate, sample_rate, ResamplerQuality::High);
+            let mut output = Vec::with_capacity(len + resampler.latency());
+            for sample in channel.iter() { resampler.push(*sample, &mut output); }
+            while output.len() < len + resampler.latency() { resampler.push(0.0, &mut output); }
+            output.drain(..resampler.latency());
+            output.truncate(len);
+            *channel = output;
         }
-        if let Ok(format) = String::from_utf8(buffer[8..12].to_vec())
+        self.sample_rate = sample_rate;
+    }
+    /// Loudness of the whole file after EBU R128.
+    pub fn measure_loudness(&self) -> LoudnessReport
+    {
+        let mut meter = LoudnessMeter::new(self.num_channel(), self.sample_rate as f64);
+        let mut frame = vec![0.0; self.num_channel()];
+        for index in 0..self.num_sample()
         {
-            if format != "WAVE"
+            for (channel, data) in frame.iter_mut().enumerate() { *data = self.audio_buffer[channel][index]; }
+            meter.process(&frame);
+        }
+        meter.report()
+    }
+    /// Scale every channel by one factor so the highest absolute sample hits the target in dB. Silent files are left as is.
+    pub fn normalize(&mut self, target_peak_db : f64)
+    {
+        let peak = self.audio_buffer.iter().flatten().fold(0.0f64, |peak, sample| peak.max(sample.abs()));
+        if peak > 0.0 { self.apply_gain_db(target_peak_db - ratio_to_db(peak)); }
+    }
+    /// Number of samples in every channel whose absolute value exceeds the threshold, such as 1.0 that saving clamps to.
+    pub fn count_clipped_samples(&self, threshold : f64) -> usize { self.audio_buffer.iter().flatten().filter(|sample| sample.abs() > threshold).count() }
+    /// Channel and sample index of the earliest sample whose absolute value exceeds the threshold, on the lowest channel at the same time.
+    pub fn first_clip_position(&self, threshold : f64) -> Option<(usize, usize)>
+    {
+        self.audio_buffer.iter().enumerate()
+            .filter_map(|(channel, data)| data.iter().position(|sample| sample.abs() > threshold).map(|index| (channel, index)))
+            .min_by_key(|&(channel, index)| (index, channel))
+    }
+    /// Apply gain in dB to every channel.
+    pub fn apply_gain_db(&mut self, db : f64)
+    {
+        let gain = db_to_ratio(db);
+        self.audio_buffer.iter_mut().flatten().for_each(|sample| *sample *= gain);
+    }
+    /// Fade in over the first samples with an equal-power curve, starting from 0.0. Channels shorter than the fade are faded over their length.
+    pub fn fade_in(&mut self, samples : usize)
+    {
+        for channel in &mut self.audio_buffer
+        {
+            let samples = samples.min(channel.len());
+            for (index, sample) in channel.iter_mut().take(samples).enumerate() { *sample *= (std::f64::consts::FRAC_PI_2 * index as f64 / samples as f64).sin(); }
+        }
+    }
+    /// Fade out over the last samples with an equal-power curve, ending at 0.0. Channels shorter than the fade are faded over their length.
+    pub fn fade_out(&mut self, samples : usize)
+    {
+        for channel in &mut self.audio_buffer
+        {
+            let samples = samples.min(channel.len());
+            let start = channel.len() - samples;
+            for (index, sample) in channel[start..].iter_mut().enumerate() { *sample *= (std::f64::consts::FRAC_PI_2 * (samples - 1 - index) as f64 / samples as f64).sin(); }
+        }
+    }
+    /// Reverse every channel in time.
+    pub fn reverse(&mut self) { self.audio_buffer.iter_mut().for_each(|channel| channel.reverse()); }
+    /// Mix every channel down to a mono file with the same sample rate and bit depth.
+    pub fn to_mono(&self, pan_law : PanLaw) -> AudioFile
+    {
+        let channels = self.num_channel().max(1) as f64;
+        let gain = match pan_law { PanLaw::Minus3dB => 1.0 / channels.sqrt(), PanLaw::Minus6dB => 1.0 / channels };
+        let mono = (0..self.num_sample()).map(|index| self.audio_buffer.iter().map(|channel| channel[index]).sum::<f64>() * gain).collect();
+        self.with_buffer(vec![mono])
+    }
+    /// Ratio of the energy of the channels averaged to mono to the average energy of the channels.
+    /// 1.0 when the channels are identical, 0.5 when uncorrelated, and near 0.0 when they cancel. 1.0 for fewer than 2 channels or silence.
+    pub fn mono_compatibility(&self) -> f64
+    {
+        let channels = self.num_channel() as f64;
+        let channel_energy = self.audio_buffer.iter().flatten().map(|sample| sample * sample).sum::<f64>() / channels;
+        if self.num_channel() < 2 || channel_energy <= 0.0 { return 1.0 }
+        let mono_energy = (0..self.num_sample()).map(|index| self.audio_buffer.iter().map(|channel| channel[index]).sum::<f64>() / channels).map(|sample| sample * sample).sum::<f64>();
+        mono_energy / channel_energy
+    }
+    /// Invert the polarity of the channel. Channels that do not exist are ignored.
+    pub fn invert_phase(&mut self, channel : usize)
+    {
+        if let Some(channel) = self.audio_buffer.get_mut(channel) { channel.iter_mut().for_each(|sample| *sample = -*sample); }
+    }
+    /// Copy a channel to a mono file with the same sample rate and bit depth. None if the channel does not exist.
+    pub fn extract_channel(&self, index : usize) -> Option<AudioFile> { self.audio_buffer.get(index).map(|channel| self.with_buffer(vec![channel.clone()])) }
+    /// Append the samples of the other file, which must have the same channel count and sample rate.
+    pub fn append(&mut self, other : &AudioFile) -> Result<(), AudioFileError>
+    {
+        if self.num_channel() != other.num_channel() { return Err(AudioFileError::ChannelMismatch(self.num_channel(), other.num_channel())) }
+        if self.sample_rate != other.sample_rate { return Err(AudioFileError::SampleRateMismatch(self.sample_rate, other.sample_rate)) }
+        for (channel, other) in self.audio_buffer.iter_mut().zip(&other.audio_buffer) { channel.extend_from_slice(other); }
+        Ok(())
+    }
+    fn info_tag(&self, tag : &str) -> Option<&str> { self.info_tags.get(tag).map(String::as_str) }
+    fn set_info_tag(&mut self, tag : &str, value : &str) { self.info_tags.insert(tag.to_string(), value.to_string()); }
+    fn with_buffer(&self, audio_buffer : Vec<Vec<f64>>) -> AudioFile
+    {
+        AudioFile
+        {
+            audio_buffer, xml_chunk : self.xml_chunk.clone(), midi_unity_note : self.midi_unity_note, midi_pitch_fraction : self.midi_pitch_fraction, info_tags : self.info_tags.clone(),
+            file_format : self.file_format, sample_rate : self.sample_rate, bit_depth : self.bit_depth, dither : self.dither, companding : self.companding, loops : self.loops.clone(), channel_mask : self.channel_mask
+        }
+    }
+    /// Copy a block of the file into processor I/O buffers, one Buffer per channel. Samples past the end of the file are filled with 0.
+    pub fn to_buffers(&self, block_start : usize, block_len : usize) -> Buffer<Buffer<f64>>
+    {
+        let mut buffers = Buffer::channels(self.num_channel(), block_len);
+        buffers.lock();
+        for (channel, data) in self.audio_buffer.iter().enumerate()
+        {
+            let buffer = &mut buffers[channel];
+            buffer.lock();
+            for index in 0..block_len { buffer[index] = data.get(block_start + index).copied().unwrap_or(0.0); }
+            buffer.unlock();
+        }
+        buffers.unlock();
+        buffers
+    }
+    /// Copy processor output buffers back into the file from the block start. Channels and samples outside the file are ignored.
+    /// Buffers of different lengths are rejected without changing the file.
+    pub fn from_buffers(&mut self, buffers : &[Buffer<f64>], block_start : usize) -> Result<(), AudioFileError>
+    {
+        check_buffer_lengths(buffers)?;
+        for (data, buffer) in self.audio_buffer.iter_mut().zip(buffers.iter())
+        {
+            for index in 0..buffer.len()
             {
-                eprintln!("ERROR: Wrong format.");
-                return
+                if let Some(sample) = data.get_mut(block_start + index) { *sample = buffer[index]; }
             }
         }
-        let index_of_data_chunk = get_index_of_chunk(buffer, "data", 12, Endianness::Little);
-        let index_of_format_chunk = get_index_of_chunk(buffer, "fmt ", 12, Endianness::Little);
+        Ok(())
+    }
+    /// Replace the audio with processor output buffers, taking the channel count and length of the buffers.
+    /// Buffers of different lengths are rejected without changing the file.
+    pub fn from_buffers_resize(&mut self, buffers : &[Buffer<f64>]) -> Result<(), AudioFileError>
+    {
+        check_buffer_lengths(buffers)?;
+        self.audio_buffer = buffers.iter().map(|buffer| buffer.to_vec()).collect();
+        Ok(())
+    }
+    /// Interleave all channels into frames of f64 samples.
+    pub fn to_interleaved_f64(&self) -> Vec<f64>
+    {
+        (0..self.num_sample()).flat_map(|index| self.audio_buffer.iter().map(move |channel| channel[index])).collect()
+    }
+    /// Interleave all channels into frames of f32 samples, as used by hosts.
+    pub fn to_interleaved_f32(&self) -> Vec<f32> { self.to_interleaved_f64().iter().map(|sample| *sample as f32).collect() }
+    /// Replace the audio with interleaved f64 samples of the number of channels. Trailing samples of an incomplete frame are dropped.
+    pub fn from_interleaved_f64(&mut self, data : &[f64], channels : usize)
+    {
+        if channels == 0 { self.audio_buffer.clear(); return }
+        self.audio_buffer = (0..channels).map(|channel| data.chunks_exact(channels).map(|frame| frame[channel]).collect()).collect();
+    }
+    /// Replace the audio with interleaved f32 samples of the number of channels. Trailing samples of an incomplete frame are dropped.
+    pub fn from_interleaved_f32(&mut self, data : &[f32], channels : usize)
+    {
+        if channels == 0 { self.audio_buffer.clear(); return }
+        self.audio_buffer = (0..channels).map(|channel| data.chunks_exact(channels).map(|frame| frame[channel] as f64).collect()).collect();
+    }
+    fn read_wav(&mut self, buffer : &[u8]) -> Result<(), AudioFileError>
+    {
+        if !matches!(buffer.get(0..4), Some(b"RIFF" | b"RF64" | b"BW64")) { return Err(AudioFileError::Corrupt("wrong header chunk id".to_string())) }
+        if buffer.get(8..12) != Some(b"WAVE") { return Err(AudioFileError::Corrupt("wrong format".to_string())) }
         let index_of_xmlchunk = get_index_of_chunk(buffer, "iXML", 12, Endianness::Little);
+        let index_of_sample_chunk = get_index_of_chunk(buffer, "smpl", 12, Endianness::Little);
+        let (Some(index_of_data_chunk), Some(index_of_format_chunk)) = (get_index_of_chunk(buffer, "data", 12, Endianness::Little), get_index_of_chunk(buffer, "fmt ", 12, Endianness::Little))
+        else { return Err(AudioFileError::Corrupt("missing fmt or data chunk".to_string())) };
+        // The basic fmt chunk holds 16 bytes, read at fixed offsets below.
+        if index_of_format_chunk + 24 > buffer.len() { return Err(AudioFileError::Corrupt("truncated fmt chunk".to_string())) }
         let _format_chunk_id = String::from_utf8(buffer[index_of_format_chunk..index_of_format_chunk + 4].to_vec());
         let _format_chunk_size = get_u32(buffer, index_of_format_chunk + 4, Endianness::Little) as usize;
-        let audio_format = WavAudioFormat::from_num(get_u16(buffer, index_of_format_chunk + 8, Endianness::Little) as usize);
+        let (audio_format, channel_mask) = get_wav_format(&buffer[index_of_format_chunk + 8..]);
+        self.channel_mask = channel_mask;
+        self.companding = match audio_format
+        {
+            Some(WavAudioFormat::ALaw) => Companding::ALaw,
+            Some(WavAudioFormat::MULaw) => Companding::MuLaw,
+            _ => Companding::None
+        };
         let num_channels = get_u16(buffer, index_of_format_chunk + 10, Endianness::Little) as usize;
         self.sample_rate = get_u32(buffer, index_of_format_chunk + 12, Endianness::Little) as usize;
         let num_bytes_per_second = get_u32(buffer, index_of_format_chunk + 16, Endianness::Little) as usize;
         let num_bytes_per_block = get_u16(buffer, index_of_format_chunk + 20, Endianness::Little) as usize;
         self.bit_depth = get_u16(buffer, index_of_format_chunk + 22, Endianness::Little) as usize;
         
-        if self.bit_depth > size_of::<f64>() * 8
-        {
-            eprintln!("ERROR: you are trying to read a {}-bit file using a {}-bit sample type", self.bit_depth, size_of::<f64>() * 8);
-            return
-        }
-        if audio_format.is_none()
+        let Some(audio_format) = audio_format else { return Err(AudioFileError::UnknownFormat) };
+        if num_channels < 1 || num_channels > 128 { return Err(AudioFileError::Corrupt(format!("invalid number of channels {}", num_channels))) }
+        if self.bit_depth < 1 || self.bit_depth > 32 { return Err(AudioFileError::UnsupportedBitDepth(self.bit_depth)) }
+        // Samples are stored in whole bytes, so 12 and 20 bits samples take 2 and 3 bytes.
+        let num_bytes_per_sample = self.bit_depth.div_ceil(8);
+        if num_bytes_per_second != num_channels * self.sample_rate * num_bytes_per_sample || num_bytes_per_block != num_channels * num_bytes_per_sample
         {
-            eprintln!("ERROR: this .WAV file is encoded in a format that this library does not support at present");
-            return
-        }
-        if num_channels < 1 || num_channels > 128
-        {
-            eprintln!("ERROR: this WAV file seems to be an invalid number of channels (or corrupted?)");
-            return
+            return Err(AudioFileError::Corrupt("inconsistent header data".to_string()))
         }
-        if num_bytes_per_second != num_channels * self.sample_rate * self.bit_depth / 8 || num_bytes_per_block != num_channels * num_bytes_per_second
-        {
-            eprintln!("ERROR: the header data in this WAV file seems to be inconsistent");
-            return
-        }
-        if self.bit_depth != 8 && self.bit_depth != 16 && self.bit_depth != 24 && self.bit_depth != 32
-        {
-            eprintln!("ERROR: this file has a bit depth that is not 8, 16, 24 or 32 bits");
-            return
-        }
-        let num_bytes_per_sample = self.bit_depth / 8;
 
         let _data_chunk_id = String::from_utf8(buffer[index_of_data_chunk..index_of_data_chunk+ 4].to_vec());
-        let data_chunk_size = get_u32(buffer, index_of_data_chunk + 4, Endianness::Little) as usize;
-        let num_samples = data_chunk_size / (num_channels * self.bit_depth / 8);
+        let mut data_chunk_size = get_u32(buffer, index_of_data_chunk + 4, Endianness::Little) as usize;
+        // RF64 keeps sizes over 32 bits in the ds64 chunk, leaving the sentinel in the data chunk.
+        if data_chunk_size == u32::MAX as usize
+        {
+            match get_index_of_chunk(buffer, "ds64", 12, Endianness::Little)
+            {
+                Some(index_of_ds64_chunk) => { data_chunk_size = get_u64(buffer, index_of_ds64_chunk + 16, Endianness::Little) as usize; }
+                None => { data_chunk_size = buffer.len() - index_of_data_chunk - 8; }
+            }
+        }
+        let num_samples = data_chunk_size / (num_channels * num_bytes_per_sample);
         let samples_start_index = index_of_data_chunk + 8;
         
         self.audio_buffer.clear();
@@ -232,73 +603,73 @@ impl AudioFile
             {
                 let sample_index = samples_start_index + num_bytes_per_block * index + channel * num_bytes_per_sample;
             
-                if sample_index + (self.bit_depth / 8) - 1 >= buffer.len()
+                if sample_index + num_bytes_per_sample - 1 >= buffer.len()
                 {
-                    eprintln!("ERROR: read file error as the metadata indicates more samples than there are in the file data");
-                    return
+                    return Err(AudioFileError::Corrupt("metadata indicates more samples than there are in the file data".to_string()))
                 }
                 
-                if self.bit_depth == 8 { self.audio_buffer[channel].push(buffer[sample_index].cast_signed() as f64 / i8::MAX as f64); }
-                else if self.bit_depth == 16
-                {
-                    let sample = get_u16(buffer, sample_index, Endianness::Little).cast_signed();
-                    let sample = sample as f64 / i16::MAX as f64;
-                    self.audio_buffer[channel].push(sample);
-                }
-                else if self.bit_depth == 24
-                {
-                    let mut sample = (((buffer[sample_index + 2] as u32) << 16) | ((buffer[sample_index + 1] as u32) << 8) | buffer[sample_index] as u32).cast_signed();
-                    if sample & 0x800000 == 0 { sample = sample | !0xFFFFFF };
-                    self.audio_buffer[channel].push(sample as f64 / 8388607.0);
-                }
-                else if self.bit_depth == 32
-                {
-                    let sample = get_u32(buffer, sample_index, Endianness::Little);
-                    if audio_format.unwrap() == WavAudioFormat::IEEEFloat { self.audio_buffer[channel].push(f32::from_bits(sample) as f64); }
-                    else { self.audio_buffer[channel].push(sample.cast_signed() as f64 / i32::MAX as f64); }
-                }
-                else
-                {
-                    eprintln!("ERROR: Wrong bit depth detected.");
-                    return;
-                }
+                self.audio_buffer[channel].push(get_wav_sample(buffer, sample_index, self.bit_depth, audio_format));
             }
         }
-        let chunk_size = get_u32(buffer, index_of_xmlchunk + 4, Endianness::Little) as usize;
-        match String::from_utf8(buffer[index_of_xmlchunk + 8..index_of_xmlchunk + 8 + chunk_size].to_vec())
+        if let Some(index_of_xmlchunk) = index_of_xmlchunk
         {
-            Ok(chunk) => { self.xml_chunk = chunk }
-            Err(error) => eprintln!("{}", error)
+            let chunk_size = get_u32(buffer, index_of_xmlchunk + 4, Endianness::Little) as usize;
+            let Some(chunk) = buffer.get(index_of_xmlchunk + 8..index_of_xmlchunk + 8 + chunk_size) else { return Err(AudioFileError::Corrupt("truncated iXML chunk".to_string())) };
+            match String::from_utf8(chunk.to_vec())
+            {
+                Ok(chunk) => { self.xml_chunk = chunk }
+                Err(error) => eprintln!("{}", error)
+            }
         }
-    }
-    fn read_aiff(&mut self, buffer : &[u8])
-    {
-        if let Ok(header_chunk_id) = String::from_utf8(buffer[0..4].to_vec())
+        self.info_tags.clear();
+        // LIST chunks are dispatched on their list type, as adtl lists hold marker labels rather than tags.
+        for index_of_list_chunk in Chunks::new(buffer, 12, Endianness::Little).filter(|&index| &buffer[index..index + 4] == b"LIST" && buffer.get(index + 8..index + 12) == Some(b"INFO"))
         {
-            if header_chunk_id != "FORM"
+            let end = (index_of_list_chunk + 8 + get_u32(buffer, index_of_list_chunk + 4, Endianness::Little) as usize).min(buffer.len());
+            for index_of_tag in Chunks::new(&buffer[..end], index_of_list_chunk + 12, Endianness::Little)
             {
-                eprintln!("ERROR: Wrong header chunk id.");
-                return
+                let tag_size = get_u32(buffer, index_of_tag + 4, Endianness::Little) as usize;
+                let Some(value) = buffer.get(index_of_tag + 8..index_of_tag + 8 + tag_size) else { break };
+                if let (Ok(tag), Ok(value)) = (std::str::from_utf8(&buffer[index_of_tag..index_of_tag + 4]), std::str::from_utf8(value))
+                {
+                    self.info_tags.insert(tag.to_string(), value.trim_end_matches('\0').to_string());
+                }
             }
         }
-        let audio_format = if let Ok(format) = String::from_utf8(buffer[8..12].to_vec())
+        self.loops.clear();
+        if let Some(index_of_sample_chunk) = index_of_sample_chunk.filter(|&index| index + 44 <= buffer.len())
         {
-            if format == "AIFF" { AIFFAudioFormat::Uncompressed } else if format == "AIFC" { AIFFAudioFormat::Compressed } else { AIFFAudioFormat::Error }
+            self.midi_unity_note = get_u32(buffer, index_of_sample_chunk + 20, Endianness::Little);
+            self.midi_pitch_fraction = get_u32(buffer, index_of_sample_chunk + 24, Endianness::Little);
+            let num_loops = get_u32(buffer, index_of_sample_chunk + 36, Endianness::Little) as usize;
+            for index in 0..num_loops
+            {
+                let index_of_loop = index_of_sample_chunk + 44 + index * 24;
+                if index_of_loop + 24 > buffer.len() { break }
+                self.loops.push(SampleLoop
+                {
+                    start : get_u32(buffer, index_of_loop + 8, Endianness::Little) as u64,
+                    end : get_u32(buffer, index_of_loop + 12, Endianness::Little) as u64,
+                    loop_type : LoopType::from_num(get_u32(buffer, index_of_loop + 4, Endianness::Little))
+                });
+            }
         }
-        else
+        Ok(())
+    }
+    fn read_aiff(&mut self, buffer : &[u8]) -> Result<(), AudioFileError>
+    {
+        if buffer.get(0..4) != Some(b"FORM") { return Err(AudioFileError::Corrupt("wrong header chunk id".to_string())) }
+        let audio_format = match buffer.get(8..12)
         {
-            eprintln!("ERROR: Wrong format.");
-            return
+            Some(b"AIFF") => AIFFAudioFormat::Uncompressed,
+            Some(b"AIFC") => AIFFAudioFormat::Compressed,
+            _ => return Err(AudioFileError::Corrupt("wrong format".to_string()))
         };
-        let index_of_comm_chunk = get_index_of_chunk(buffer, "COMM", 12, Endianness::Big);
-        let index_of_sound_data_chunk = get_index_of_chunk(buffer, "SSND", 12, Endianness::Big);
         let index_of_xmlchunk = get_index_of_chunk(buffer, "iXML", 12, Endianness::Big);
-        
-        if index_of_sound_data_chunk == 0 || index_of_comm_chunk == 0 || audio_format == AIFFAudioFormat::Error
-        {
-            eprintln!("ERROR: this doesn't seem to be a valid AIFF file");
-            return
-        }
+        let (Some(index_of_comm_chunk), Some(index_of_sound_data_chunk)) = (get_index_of_chunk(buffer, "COMM", 12, Endianness::Big), get_index_of_chunk(buffer, "SSND", 12, Endianness::Big))
+        else { return Err(AudioFileError::Corrupt("missing COMM or SSND chunk".to_string())) };
+        // COMM holds 18 bytes and SSND starts with offset and block size, read at fixed offsets below.
+        if index_of_comm_chunk + 26 > buffer.len() || index_of_sound_data_chunk + 16 > buffer.len() { return Err(AudioFileError::Corrupt("truncated COMM or SSND chunk".to_string())) }
 
         let _comm_chunk_id  = String::from_utf8(buffer[index_of_comm_chunk..index_of_comm_chunk + 4].to_vec());
         let _comm_chunk_size = get_u32(buffer, index_of_comm_chunk + 4, Endianness::Big) as usize;
@@ -308,39 +679,21 @@ impl AudioFile
         self.bit_depth = get_u16(buffer, index_of_comm_chunk + 14, Endianness::Big) as usize;
         self.sample_rate = get_aiff_sample_rate(buffer, index_of_comm_chunk + 16);
         
-        if self.bit_depth > size_of::<f64>() * 8
-        {
-            eprintln!("ERROR: you are trying to read a {}-bit file using a {}-bit sample type", self.bit_depth, size_of::<f64>() * 8);
-            return
-        }
-        if self.sample_rate == 0
-        {
-            eprintln!("ERROR: this AIFF file has an unsupported sample rate");
-            return
-        }
-        if num_channels < 1 ||num_channels > 2
-        {
-            eprintln!("ERROR: this AIFF file seems to be neither mono nor stereo (perhaps multi-track, or corrupted?)");
-            return
-        }
-        if self.bit_depth != 8 && self.bit_depth != 16 && self.bit_depth != 24 && self.bit_depth != 32
-        {
-            eprintln!("ERROR: this file has a bit depth that is not 8, 16, 24 or 32 bits");
-            return
-        }
+        if self.sample_rate == 0 { return Err(AudioFileError::Corrupt("unsupported sample rate".to_string())) }
+        if num_channels < 1 || num_channels > 2 { return Err(AudioFileError::Corrupt(format!("invalid number of channels {}", num_channels))) }
+        if self.bit_depth < 1 || self.bit_depth > 32 { return Err(AudioFileError::UnsupportedBitDepth(self.bit_depth)) }
         let _sound_data_chunk_id =  String::from_utf8(buffer[index_of_sound_data_chunk..index_of_sound_data_chunk + 4].to_vec());
         let sound_data_chunk_size = get_u32(buffer, index_of_sound_data_chunk + 4, Endianness::Big) as usize;
         let offset = get_u32(buffer, index_of_sound_data_chunk + 8, Endianness::Big) as usize;
         let _block_size = get_u32(buffer, index_of_sound_data_chunk + 12, Endianness::Big) as usize;
-        let num_bytes_per_sample = self.bit_depth / 8;
+        let num_bytes_per_sample = self.bit_depth.div_ceil(8);
         let num_bytes_per_frame = num_bytes_per_sample * num_channels;
         let total_num_audio_sample_bytes = num_samples_per_channel * num_bytes_per_frame;
         let samples_start_index = index_of_sound_data_chunk + 16 + offset;
             
-        if sound_data_chunk_size - 8 != total_num_audio_sample_bytes || total_num_audio_sample_bytes > buffer.len() - samples_start_index
+        if sound_data_chunk_size.checked_sub(8) != Some(total_num_audio_sample_bytes) || Some(total_num_audio_sample_bytes) > buffer.len().checked_sub(samples_start_index)
         {
-            eprintln!("ERROR: the metadatafor this file doesn't seem right");
-            return
+            return Err(AudioFileError::Corrupt("sound data size does not match the metadata".to_string()))
         }
         self.audio_buffer.clear();
         self.audio_buffer.resize(num_channels, vec![]);
@@ -351,93 +704,161 @@ impl AudioFile
             {
                 let sample_index = samples_start_index + (num_bytes_per_frame * index) + channel * num_bytes_per_sample;
             
-                if sample_index + self.bit_depth / 8 - 1 >= buffer.len()
+                if sample_index + num_bytes_per_sample - 1 >= buffer.len()
                 {
-                    eprintln!("ERROR: read file error as the metadata indicates more samples than there are in the file data");
-                    return
+                    return Err(AudioFileError::Corrupt("metadata indicates more samples than there are in the file data".to_string()))
                 }
                 
-                if self.bit_depth == 8 { self.audio_buffer[channel].push(buffer[sample_index].cast_signed() as f64 / i8::MAX as f64); }
-                else if self.bit_depth == 16 { self.audio_buffer[channel].push(get_u16(buffer, sample_index, Endianness::Big) as f64 / u16::MAX as f64); }
-                else if self.bit_depth == 24
-                {
-                    let mut sample = ((buffer[sample_index] as i32) << 16) | ((buffer[sample_index + 1] as i32) << 8) | buffer[sample_index + 2] as i32;
-                    
-                    if sample & 0x800000 == 0 { sample = sample | !0xFFFFFF; }
-                    self.audio_buffer[channel].push(sample as f64 / 8388607.0);
-                }
-                else if self.bit_depth == 32
-                {
-                    let sample = get_u32(buffer, sample_index, Endianness::Big);
-                    
-                    if audio_format == AIFFAudioFormat::Compressed { self.audio_buffer[channel].push(f32::from_bits(sample) as f64); }
-                    else { self.audio_buffer[channel].push(sample.cast_signed() as f64 / i32::MAX as f64) }
-                }
-                else
-                {
-                    eprintln!("ERROR: Wrong bit depth detected.");
-                    return;
-                }
+                if self.bit_depth == 32 && audio_format == AIFFAudioFormat::Compressed { self.audio_buffer[channel].push(f32::from_bits(get_u32(buffer, sample_index, Endianness::Big)) as f64); }
+                else { self.audio_buffer[channel].push(from_pcm_bytes(&buffer[sample_index..], self.bit_depth, Endianness::Big)); }
             }
         }
-        let chunk_size = get_u32(buffer, index_of_xmlchunk + 4, Endianness::Little) as usize;
-        if let Ok(xml) = String::from_utf8(buffer[index_of_xmlchunk + 8..index_of_xmlchunk + 8 + chunk_size].to_vec()) { self.xml_chunk = xml; }
+        if let Some(index_of_xmlchunk) = index_of_xmlchunk
+        {
+            let chunk_size = get_u32(buffer, index_of_xmlchunk + 4, Endianness::Big) as usize;
+            let Some(chunk) = buffer.get(index_of_xmlchunk + 8..index_of_xmlchunk + 8 + chunk_size) else { return Err(AudioFileError::Corrupt("truncated iXML chunk".to_string())) };
+            if let Ok(xml) = String::from_utf8(chunk.to_vec()) { self.xml_chunk = xml; }
+        }
+        Ok(())
+    }
+    #[cfg(feature = "flac")]
+    fn read_flac(&mut self, buffer : &[u8]) -> Result<(), AudioFileError>
+    {
+        let mut reader = claxon::FlacReader::new(std::io::Cursor::new(buffer)).map_err(|error| AudioFileError::Corrupt(error.to_string()))?;
+        let info = reader.streaminfo();
+        let num_channels = info.channels as usize;
+        self.sample_rate = info.sample_rate as usize;
+        self.bit_depth = info.bits_per_sample as usize;
+        // Same scale as PCM of the bit depth in WAV, so a lossless FLAC loads to the same samples as its WAV.
+        let scale = full_scale(self.bit_depth);
+
+        self.audio_buffer.clear();
+        self.audio_buffer.resize(num_channels, Vec::with_capacity(info.samples.unwrap_or(0) as usize));
+        for (index, sample) in reader.samples().enumerate()
+        {
+            let sample = sample.map_err(|error| AudioFileError::Corrupt(error.to_string()))?;
+            self.audio_buffer[index % num_channels].push(sample as f64 / scale);
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "flac"))]
+    fn read_flac(&mut self, _buffer : &[u8]) -> Result<(), AudioFileError> { Err(AudioFileError::UnknownFormat) }
+    fn save_wav(&self, path : &str) -> Result<(), AudioFileError>
+    {
+        let data_chunk_size = self.num_sample() * self.num_channel() * self.bit_depth.div_ceil(8);
+        self.save_wav_internal(path, data_chunk_size > u32::MAX as usize)
     }
-    fn save_wav(&self, path : &str)
+    /// Save as RF64 WAV, whose ds64 chunk holds sizes over 4GB, logging any error.
+    pub fn save_rf64(&self, path : &str)
+    {
+        if let Err(error) = self.save_wav_internal(path, true) { eprintln!("ERROR: couldn't save {} : {}", path, error); }
+    }
+    fn save_wav_internal(&self, path : &str, rf64 : bool) -> Result<(), AudioFileError>
     {
         let mut buffer = vec![];
 
-        let data_chunk_size = self.num_sample() * self.num_channel() * self.bit_depth / 8;
-        let audio_format =  WavAudioFormat::PCM;
-        let format_chunk_size = 16;
+        let (audio_format, bit_depth) = match self.companding
+        {
+            Companding::None => (WavAudioFormat::PCM, self.bit_depth),
+            Companding::ALaw => (WavAudioFormat::ALaw, 8),
+            Companding::MuLaw => (WavAudioFormat::MULaw, 8)
+        };
+        if !(1..=32).contains(&bit_depth) { return Err(AudioFileError::UnsupportedBitDepth(bit_depth)) }
+        // Bit depths between whole bytes are stored left-justified in whole bytes.
+        let num_bytes_per_sample = bit_depth.div_ceil(8);
+        let data_chunk_size = self.num_sample() * self.num_channel() * num_bytes_per_sample;
+        // Formats other than PCM carry cbSize, even when it is 0.
+        let format_chunk_size = if audio_format == WavAudioFormat::PCM { 16 } else { 18 };
         let i_xmlchunk_size = self.xml_chunk.len();
+        let sample_chunk_size = if self.loops.is_empty() { 0 } else { 36 + 24 * self.loops.len() };
+        let info_tags = self.info_tags.iter().filter(|(tag, _)| tag.len() == 4).collect::<Vec<_>>();
+        // Each tag is NUL terminated, with a pad byte after odd sized tags.
+        let info_chunk_size = if info_tags.is_empty() { 0 } else { 4 + info_tags.iter().map(|(_, value)| 8 + (value.len() + 1) + (value.len() + 1) % 2).sum::<usize>() };
 
-        set_string(&mut buffer, "RIFF");
-        let mut file_size_in_bytes = 4 + format_chunk_size + 8 + 8 + data_chunk_size;
-        if i_xmlchunk_size > 0 { file_size_in_bytes += 8 + i_xmlchunk_size; }
-        set_u32(&mut buffer, file_size_in_bytes as u32, Endianness::Little);
-        set_string(&mut buffer, "WAVE");
+        // Chunks are word aligned, so odd sized data and iXML chunks are followed by a pad byte.
+        let mut file_size_in_bytes = 4 + format_chunk_size + 8 + 8 + data_chunk_size + (data_chunk_size & 1);
+        if info_chunk_size > 0 { file_size_in_bytes += 8 + info_chunk_size; }
+        if i_xmlchunk_size > 0 { file_size_in_bytes += 8 + i_xmlchunk_size + (i_xmlchunk_size & 1); }
+        if sample_chunk_size > 0 { file_size_in_bytes += 8 + sample_chunk_size; }
+        if rf64
+        {
+            file_size_in_bytes += 8 + 28;
+            set_string(&mut buffer, "RF64");
+            set_u32(&mut buffer, u32::MAX, Endianness::Little);
+            set_string(&mut buffer, "WAVE");
+            set_string(&mut buffer, "ds64");
+            set_u32(&mut buffer, 28, Endianness::Little);
+            set_u64(&mut buffer, file_size_in_bytes as u64, Endianness::Little);
+            set_u64(&mut buffer, data_chunk_size as u64, Endianness::Little);
+            set_u64(&mut buffer, self.num_sample() as u64, Endianness::Little);
+            set_u32(&mut buffer, 0, Endianness::Little);
+        }
+        else
+        {
+            set_string(&mut buffer, "RIFF");
+            set_u32(&mut buffer, file_size_in_bytes as u32, Endianness::Little);
+            set_string(&mut buffer, "WAVE");
+        }
         set_string(&mut buffer, "fmt ");
         set_u32(&mut buffer, format_chunk_size as u32, Endianness::Little);
         set_u16(&mut buffer, audio_format.to_num() as u16, Endianness::Little);
         set_u16(&mut buffer, self.num_channel() as u16, Endianness::Little);
         set_u32(&mut buffer, self.sample_rate as u32, Endianness::Little);
-        set_u32(&mut buffer, (self.num_channel() * self.sample_rate * self.bit_depth / 8) as u32, Endianness::Little);
-        set_u16(&mut buffer, (self.num_channel() * (self.bit_depth / 8)) as u16, Endianness::Little);
-        set_u16(&mut buffer, self.bit_depth as u16, Endianness::Little);
+        set_u32(&mut buffer, (self.num_channel() * self.sample_rate * num_bytes_per_sample) as u32, Endianness::Little);
+        set_u16(&mut buffer, (self.num_channel() * num_bytes_per_sample) as u16, Endianness::Little);
+        set_u16(&mut buffer, bit_depth as u16, Endianness::Little);
+        if format_chunk_size == 18 { set_u16(&mut buffer, 0, Endianness::Little); }
         set_string (&mut buffer, "data");
-        set_u32(&mut buffer, data_chunk_size as u32, Endianness::Little);
+        set_u32(&mut buffer, if rf64 { u32::MAX } else { data_chunk_size as u32 }, Endianness::Little);
 
+        // Companded steps are not uniform, so dither of a linear LSB does not apply.
+        let mut ditherer = Ditherer::new(if self.companding == Companding::None { self.dither } else { Dither::None }, bit_depth, self.num_channel());
         for index in 0..self.num_sample()
         {
             for channel in 0..self.num_channel()
             {
-                let sample = self.audio_buffer[channel][index].clamp(-1.0, 1.0);
-                if self.bit_depth == 8 { buffer.push(((sample * i8::MAX as f64) as i8).cast_unsigned()); }
-                else if self.bit_depth == 16
-                {
-                    set_u16(&mut buffer, ((sample * i16::MAX as f64) as i16).cast_unsigned(), Endianness::Little);
-                }
-                else if self.bit_depth == 24
-                {
-                    let mut bytes = [0;3];
-                    let sample = (sample * 8388607.0) as i32;
-
-                    bytes[2] = (sample >> 16 & 0xFF)  as u8;
-                    bytes[1] = (sample >>  8 & 0xFF) as u8;
-                    bytes[0] = (sample & 0xFF) as u8;
-                    
-                    buffer.extend_from_slice(&bytes);
-                }
-                else if self.bit_depth == 32
-                {
-                    set_u32(&mut buffer, ((sample * i32::MAX as f64) as i32).cast_unsigned(), Endianness::Little);
-                }
-                else
-                {
-                    eprintln!("ERROR: Trying to write a file with unsupported bit depth");
-                    return;
-                }
+                let sample = ditherer.process(channel, self.audio_buffer[channel][index]).clamp(-1.0, 1.0);
+                if audio_format == WavAudioFormat::ALaw { buffer.push(linear_to_alaw((sample * i16::MAX as f64).round() as i16)); }
+                else if audio_format == WavAudioFormat::MULaw { buffer.push(linear_to_mulaw((sample * i16::MAX as f64).round() as i16)); }
+                else { to_wav_bytes(sample, bit_depth, &mut buffer); }
+            }
+        }
+        buffer.resize(buffer.len() + (data_chunk_size & 1), 0);
+        if sample_chunk_size > 0
+        {
+            set_string(&mut buffer, "smpl");
+            set_u32(&mut buffer, sample_chunk_size as u32, Endianness::Little);
+            set_u32(&mut buffer, 0, Endianness::Little);
+            set_u32(&mut buffer, 0, Endianness::Little);
+            set_u32(&mut buffer, (1_000_000_000 / self.sample_rate.max(1)) as u32, Endianness::Little);
+            set_u32(&mut buffer, self.midi_unity_note, Endianness::Little);
+            set_u32(&mut buffer, self.midi_pitch_fraction, Endianness::Little);
+            set_u32(&mut buffer, 0, Endianness::Little);
+            set_u32(&mut buffer, 0, Endianness::Little);
+            set_u32(&mut buffer, self.loops.len() as u32, Endianness::Little);
+            set_u32(&mut buffer, 0, Endianness::Little);
+            for (index, sample_loop) in self.loops.iter().enumerate()
+            {
+                set_u32(&mut buffer, index as u32, Endianness::Little);
+                set_u32(&mut buffer, sample_loop.loop_type.to_num(), Endianness::Little);
+                set_u32(&mut buffer, sample_loop.start.min(u32::MAX as u64) as u32, Endianness::Little);
+                set_u32(&mut buffer, sample_loop.end.min(u32::MAX as u64) as u32, Endianness::Little);
+                set_u32(&mut buffer, 0, Endianness::Little);
+                set_u32(&mut buffer, 0, Endianness::Little);
+            }
+        }
+        if info_chunk_size > 0
+        {
+            set_string(&mut buffer, "LIST");
+            set_u32(&mut buffer, info_chunk_size as u32, Endianness::Little);
+            set_string(&mut buffer, "INFO");
+            for (tag, value) in info_tags
+            {
+                let tag_size = value.len() + 1;
+                set_string(&mut buffer, tag);
+                set_u32(&mut buffer, tag_size as u32, Endianness::Little);
+                set_string(&mut buffer, value);
+                buffer.resize(buffer.len() + 1 + tag_size % 2, 0);
             }
         }
         if i_xmlchunk_size > 0
@@ -445,35 +866,32 @@ impl AudioFile
             set_string(&mut buffer, "iXML");
             set_u32(&mut buffer, i_xmlchunk_size as u32, Endianness::Little);
             set_string(&mut buffer, &self.xml_chunk);
+            buffer.resize(buffer.len() + (i_xmlchunk_size & 1), 0);
         }
-        if file_size_in_bytes != buffer.len() - 8 || data_chunk_size != (self.num_sample() * self.num_channel() * self.bit_depth / 8)
+        if file_size_in_bytes != buffer.len() - 8 || data_chunk_size != (self.num_sample() * self.num_channel() * num_bytes_per_sample)
         {
-            eprintln!("ERROR: file size doesn't match");
-            return
+            return Err(AudioFileError::Corrupt("file size doesn't match".to_string()))
         }
-        if let Ok(mut file) = std::fs::File::create(path)
-        {
-            if let Err(error) = std::io::Write::write(&mut file, &buffer)
-            {
-                eprintln!("ERROR: couldn't save file to {} from error : {}", path, error);
-            }
-        } else { eprintln!("ERROR: couldn't create file to {}", path); }
+        std::fs::write(path, &buffer)?;
+        Ok(())
     }
-    fn save_aiff(&self, path : &str)
+    fn save_aiff(&self, path : &str) -> Result<(), AudioFileError>
     {
         let mut buffer = vec![];
     
-        let num_bytes_per_sample = self.bit_depth / 8;
+        if !(1..=32).contains(&self.bit_depth) { return Err(AudioFileError::UnsupportedBitDepth(self.bit_depth)) }
+        let num_bytes_per_sample = self.bit_depth.div_ceil(8);
         let num_bytes_per_frame = num_bytes_per_sample * self.num_channel();
         let total_num_audio_sample_bytes = self.num_sample() * num_bytes_per_frame;
         let sound_data_chunk_size = total_num_audio_sample_bytes + 8;
         let i_xmlchunk_size = self.xml_chunk.len();
         
         set_string(&mut buffer, "FORM");
-        let mut file_size_in_bytes = 4 + 26 + 16 + total_num_audio_sample_bytes;
+        // Chunks are word aligned, so odd sized sound data and iXML chunks are followed by a pad byte.
+        let mut file_size_in_bytes = 4 + 26 + 16 + total_num_audio_sample_bytes + (total_num_audio_sample_bytes & 1);
         if i_xmlchunk_size > 0
         {
-            file_size_in_bytes += 8 + i_xmlchunk_size;
+            file_size_in_bytes += 8 + i_xmlchunk_size + (i_xmlchunk_size & 1);
         }
     
         set_u32(&mut buffer, file_size_in_bytes as u32, Endianness::Big);
@@ -490,50 +908,133 @@ impl AudioFile
         set_u32(&mut buffer, 0, Endianness::Big);
         set_u32(&mut buffer, 0, Endianness::Big);
         
+        let mut ditherer = Ditherer::new(self.dither, self.bit_depth, self.num_channel());
         for index in 0..self.num_sample()
         {
             for channel in 0..self.num_channel()
             {
-                let sample = self.audio_buffer[channel][index].clamp(-1.0, 1.0);
-                if self.bit_depth == 8 { buffer.push(((sample * i8::MAX as f64) as i8).cast_unsigned()); }
-                else if self.bit_depth == 16
-                {
-                    set_u16(&mut buffer, ((sample * i16::MAX as f64) as i16).cast_unsigned(), Endianness::Big);
-                }
-                else if self.bit_depth == 24
-                {
-                    let mut bytes = [0;3];
-                    let sample = (sample * 8388607.0) as i32;
-
-                    bytes[0] = (sample >> 16 & 0xFF) as u8;
-                    bytes[1] = (sample >> 8 & 0xFF) as u8;
-                    bytes[2] = (sample & 0xFF) as u8;
-                    
-                    buffer.extend(&bytes);
-                }
-                else if self.bit_depth == 32 { set_u32(&mut buffer, ((sample * i32::MAX as f64) as i32).cast_unsigned(), Endianness::Big); }
-                else
-                {
-                    eprintln!("Trying to write a file with unsupported bit depth");
-                    return
-                }
+                let sample = ditherer.process(channel, self.audio_buffer[channel][index]).clamp(-1.0, 1.0);
+                to_pcm_bytes(sample, self.bit_depth, Endianness::Big, &mut buffer);
             }
         }
+        buffer.resize(buffer.len() + (total_num_audio_sample_bytes & 1), 0);
         if i_xmlchunk_size > 0
         {
             set_string(&mut buffer, "iXML");
             set_u32(&mut buffer, i_xmlchunk_size as u32, Endianness::Big);
             set_string(&mut buffer, &self.xml_chunk);
+            buffer.resize(buffer.len() + (i_xmlchunk_size & 1), 0);
         }
-        if let Ok(mut file) = std::fs::File::create(path)
+        std::fs::write(path, &buffer)?;
+        Ok(())
+    }
+}
+/// WAV reader that streams the data chunk block by block instead of loading the whole file.
+pub struct WavStreamReader
+{
+    file : std::fs::File,
+    audio_format : WavAudioFormat,
+    num_channels : usize,
+    sample_rate : usize,
+    bit_depth : usize,
+    data_start : u64,
+    num_frames : usize,
+    position : usize,
+    block : Vec<u8>
+}
+impl WavStreamReader
+{
+    /// Open WAV file and parse the header, walking chunks by seeking over them.
+    pub fn open(path : &str) -> Result<Self, AudioFileError>
+    {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut file = std::fs::File::open(path)?;
+        let mut header = [0u8; 12];
+        file.read_exact(&mut header)?;
+        if !matches!(&header[0..4], b"RIFF" | b"RF64" | b"BW64") || &header[8..12] != b"WAVE" { return Err(AudioFileError::Corrupt("not a RIFF WAVE file".to_string())) }
+
+        let mut format = None;
+        let mut data = None;
+        let mut ds64_data_size = None;
+        let mut position = 12u64;
+        let mut chunk = [0u8; 8];
+        while format.is_none() || data.is_none()
         {
-            if let Err(error) = std::io::Write::write(&mut file, &buffer)
+            file.seek(SeekFrom::Start(position))?;
+            if file.read_exact(&mut chunk).is_err() { break }
+            let chunk_size = get_u32(&chunk, 4, Endianness::Little) as u64;
+            if &chunk[0..4] == b"fmt "
+            {
+                // WAVE_FORMAT_EXTENSIBLE is the largest known format at 40 bytes, so the rest is skipped with the chunk.
+                let mut fmt = vec![0u8; chunk_size.clamp(16, 40) as usize];
+                file.read_exact(&mut fmt)?;
+                format = Some(fmt);
+            }
+            else if &chunk[0..4] == b"ds64"
+            {
+                let mut ds64 = [0u8; 24];
+                file.read_exact(&mut ds64)?;
+                ds64_data_size = Some(get_u64(&ds64, 8, Endianness::Little));
+            }
+            else if &chunk[0..4] == b"data"
             {
-                eprintln!("ERROR: couldn't save file to {} from error : {}", path, error);
+                // RF64 keeps sizes over 32 bits in the ds64 chunk, leaving the sentinel in the data chunk.
+                data = Some((position + 8, if chunk_size == u32::MAX as u64 { ds64_data_size.unwrap_or(chunk_size) } else { chunk_size }));
             }
-        } else { eprintln!("ERROR: couldn't create file to {}", path); }
+            // Chunks are word aligned, so an odd sized chunk is followed by a pad byte.
+            position += 8 + chunk_size + (chunk_size & 1);
+        }
+        let (Some(format), Some((data_start, data_size))) = (format, data) else { return Err(AudioFileError::Corrupt("missing fmt or data chunk".to_string())) };
+
+        let audio_format = get_wav_format(&format).0.ok_or(AudioFileError::Corrupt("unsupported encoding".to_string()))?;
+        let num_channels = get_u16(&format, 2, Endianness::Little) as usize;
+        let sample_rate = get_u32(&format, 4, Endianness::Little) as usize;
+        let bit_depth = get_u16(&format, 14, Endianness::Little) as usize;
+        if num_channels < 1 || !(1..=32).contains(&bit_depth) { return Err(AudioFileError::Corrupt("invalid channel count or bit depth".to_string())) }
+        if data_start.saturating_add(data_size) > file.metadata()?.len() { return Err(AudioFileError::Corrupt("metadata indicates more samples than there are in the file data".to_string())) }
+        let num_frames = (data_size / (num_channels * bit_depth.div_ceil(8)) as u64) as usize;
+
+        Ok(Self { file, audio_format, num_channels, sample_rate, bit_depth, data_start, num_frames, position : 0, block : vec![] })
+    }
+    /// Returns number of channels.
+    pub fn num_channel(&self) -> usize { self.num_channels }
+    /// Returns sample rate.
+    pub fn sample_rate(&self) -> usize { self.sample_rate }
+    /// Returns bit depth.
+    pub fn bit_depth(&self) -> usize { self.bit_depth }
+    /// Returns number of frames in the file.
+    pub fn num_frames(&self) -> usize { self.num_frames }
+    /// Returns frame to read next.
+    pub fn position(&self) -> usize { self.position }
+    /// Move to the frame to read next.
+    pub fn seek(&mut self, frame : usize) { self.position = frame.min(self.num_frames); }
+    /// Read up to the frames as a buffer for each channel. None at the end of the file.
+    pub fn read_block(&mut self, frames : usize) -> Option<Vec<Buffer<f64>>>
+    {
+        use std::io::{Read, Seek, SeekFrom};
+        let frames = frames.min(self.num_frames - self.position);
+        if frames == 0 { return None }
+        let num_bytes_per_sample = self.bit_depth.div_ceil(8);
+        let num_bytes_per_frame = num_bytes_per_sample * self.num_channels;
+        self.block.resize(frames * num_bytes_per_frame, 0);
+        if let Err(error) = self.file.seek(SeekFrom::Start(self.data_start + (self.position * num_bytes_per_frame) as u64)).and_then(|_| self.file.read_exact(&mut self.block))
+        {
+            eprintln!("ERROR: {}", error);
+            return None
+        }
+        self.position += frames;
+
+        let mut buffers : Vec<Buffer<f64>> = (0..self.num_channels).map(|_| Buffer::new(frames)).collect();
+        for (channel, buffer) in buffers.iter_mut().enumerate()
+        {
+            buffer.lock();
+            for index in 0..frames { buffer[index] = get_wav_sample(&self.block, index * num_bytes_per_frame + channel * num_bytes_per_sample, self.bit_depth, self.audio_format); }
+            buffer.unlock();
+        }
+        Some(buffers)
     }
 }
+
 impl Default for AudioFile
 {
     fn default() -> Self
@@ -542,9 +1043,16 @@ impl Default for AudioFile
         {
             audio_buffer: vec![vec![]],
             xml_chunk: String::new(),
+            midi_unity_note: 60,
+            midi_pitch_fraction: 0,
+            info_tags: InfoTags::new(),
             file_format: FileFormat::NotLoaded,
             sample_rate: 44100,
-            bit_depth: 16
+            bit_depth: 16,
+            dither: Dither::None,
+            companding: Companding::None,
+            loops: vec![],
+            channel_mask: None
         }
     }
 }
@@ -556,6 +1064,17 @@ fn ten_byte_match(buffer1 : &[u8], start1 : usize, buffer2 : &[u8], start2 : usi
     true
 }
 
+/// Check that all buffers have the length of the first.
+fn check_buffer_lengths(buffers : &[Buffer<f64>]) -> Result<(), AudioFileError>
+{
+    let Some(first) = buffers.first() else { return Ok(()) };
+    match buffers.iter().find(|buffer| buffer.len() != first.len())
+    {
+        Some(buffer) => Err(AudioFileError::LengthMismatch(first.len(), buffer.len())),
+        None => Ok(())
+    }
+}
+
 #[inline]
 fn get_aiff_sample_rate(buffer : &[u8], start : usize) -> usize
 {
@@ -581,31 +1100,113 @@ fn set_aiff_sample_rate(buffer : &mut Vec<u8>, sample_rate : usize)
 #[inline]
 fn set_string(buffer : &mut Vec<u8>, string : &str) { buffer.extend_from_slice(string.as_bytes()); }
 
-#[inline]
-fn get_index_of_chunk(buffer : &[u8], chunk : &str, start : usize, endianness : Endianness) -> usize
+/// Iterator over the chunks of a RIFF or AIFF file, yielding the index of each chunk header.
+struct Chunks<'a>
 {
-    let datalen = 4;
-
-    if chunk.len() != datalen
+    buffer : &'a [u8],
+    index : usize,
+    endianness : Endianness
+}
+impl<'a> Chunks<'a>
+{
+    fn new(buffer : &'a [u8], start : usize, endianness : Endianness) -> Self { Self { buffer, index : start, endianness } }
+}
+impl Iterator for Chunks<'_>
+{
+    type Item = usize;
+    fn next(&mut self) -> Option<usize>
     {
-        eprintln!("ERROR: Invalid chunk header ID string");
-        return 0;
+        let index = self.index;
+        if index.checked_add(8)? > self.buffer.len() { return None }
+        let mut chunk_size = get_u32(self.buffer, index + 4, self.endianness) as usize;
+        // RF64 leaves the sentinel in the data chunk and keeps its size in the ds64 chunk that comes first.
+        if chunk_size == u32::MAX as usize && &self.buffer[index..index + 4] == b"data" && self.buffer.get(12..16) == Some(b"ds64")
+        {
+            chunk_size = get_u64(self.buffer, 28, self.endianness) as usize;
+        }
+        // Chunks are word aligned, so an odd sized chunk is followed by a pad byte.
+        self.index = index.saturating_add(8).saturating_add(chunk_size).saturating_add(chunk_size & 1);
+        Some(index)
     }
+}
 
-    let mut index = start;
-    while index < buffer.len() - datalen
+#[inline]
+fn get_index_of_chunk(buffer : &[u8], chunk : &str, start : usize, endianness : Endianness) -> Option<usize>
+{
+    if chunk.len() != 4
     {
-        if &buffer[index..index + datalen] == chunk.as_bytes() { return index }
-        index += datalen;
-        if (index + 4) >= buffer.len()
-        {
-            eprintln!("ERROR: Chunk header ID not found.");
-            return 0;
-        }
-        let chunk_size = get_u32(buffer, index, endianness) as usize;
-        index += datalen + chunk_size;
+        eprintln!("ERROR: Invalid chunk header ID string");
+        return None
     }
-    return 0;
+    Chunks::new(buffer, start, endianness).find(|&index| &buffer[index..index + 4] == chunk.as_bytes())
+}
+
+/// Read encoding of the fmt chunk body, resolving WAVE_FORMAT_EXTENSIBLE to its SubFormat along with the channel mask.
+#[inline]
+fn get_wav_format(format : &[u8]) -> (Option<WavAudioFormat>, Option<u32>)
+{
+    let audio_format = WavAudioFormat::from_num(get_u16(format, 0, Endianness::Little) as usize);
+    if audio_format != Some(WavAudioFormat::Extensible) { return (audio_format, None) }
+    // cbSize, wValidBitsPerSample, dwChannelMask and SubFormat follow the 16 bytes of the basic fmt chunk.
+    if format.len() < 40 || get_u16(format, 16, Endianness::Little) < 22 || format[26..40] != KSDATAFORMAT_SUBTYPE_TAIL { return (None, None) }
+    let sub_format = WavAudioFormat::from_num(get_u16(format, 24, Endianness::Little) as usize).filter(|&sub_format| sub_format != WavAudioFormat::Extensible);
+    (sub_format, Some(get_u32(format, 20, Endianness::Little)))
+}
+
+/// Read WAV sample at the bit depth, normalized from -1.0 to 1.0.
+#[inline]
+fn get_wav_sample(buffer : &[u8], start : usize, bit_depth : usize, audio_format : WavAudioFormat) -> f64
+{
+    if audio_format == WavAudioFormat::ALaw { alaw_to_linear(buffer[start]) as f64 / i16::MAX as f64 }
+    else if audio_format == WavAudioFormat::MULaw { mulaw_to_linear(buffer[start]) as f64 / i16::MAX as f64 }
+    else if bit_depth == 32 && audio_format == WavAudioFormat::IEEEFloat { f32::from_bits(get_u32(buffer, start, Endianness::Little)) as f64 }
+    else { from_wav_bytes(&buffer[start..], bit_depth) }
+}
+
+/// Expand G.711 A-law byte to 16 bits linear sample.
+#[inline]
+fn alaw_to_linear(alaw : u8) -> i16
+{
+    let alaw = alaw ^ 0x55;
+    let segment = (alaw & 0x70) >> 4;
+    let mut sample = ((alaw & 0x0F) as i16) << 4;
+    sample += if segment == 0 { 8 } else { 0x108 };
+    if segment > 1 { sample <<= segment - 1; }
+    if alaw & 0x80 != 0 { sample } else { -sample }
+}
+
+/// Compress 16 bits linear sample to G.711 A-law byte.
+#[inline]
+fn linear_to_alaw(sample : i16) -> u8
+{
+    // A-law works on 13 bits, with the sign folded so that negative samples mirror positive ones.
+    let sample = sample >> 3;
+    let (mask, magnitude) = if sample >= 0 { (0xD5, sample) } else { (0x55, -sample - 1) };
+    let segment = (0..8).find(|&segment| magnitude < 0x20 << segment).unwrap_or(8);
+    if segment >= 8 { return 0x7F ^ mask }
+    let mantissa = if segment < 2 { (magnitude >> 1) & 0x0F } else { (magnitude >> segment) & 0x0F };
+    ((segment << 4) as u8 | mantissa as u8) ^ mask
+}
+
+/// Expand G.711 mu-law byte to 16 bits linear sample.
+#[inline]
+fn mulaw_to_linear(mulaw : u8) -> i16
+{
+    let mulaw = !mulaw;
+    let exponent = (mulaw >> 4) & 0x07;
+    let sample = ((((mulaw & 0x0F) as i16) << 3) + 0x84) << exponent;
+    if mulaw & 0x80 != 0 { 0x84 - sample } else { sample - 0x84 }
+}
+
+/// Compress 16 bits linear sample to G.711 mu-law byte.
+#[inline]
+fn linear_to_mulaw(sample : i16) -> u8
+{
+    let sign = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = (sample as i32).abs().min(32635) + 0x84;
+    let exponent = (7..15).rev().find(|&bit| magnitude & (1 << bit) != 0).map_or(0, |bit| bit - 7);
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
 }
 
 #[inline]
@@ -617,11 +1218,11 @@ fn get_u32(buffer : &[u8], start : usize, endianness : Endianness) -> u32
         {
             Endianness::Big =>
             {
-                ((buffer[start + 3] as u32) << 24) | ((buffer[start + 2] as u32) << 16) | ((buffer[start + 1] as u32) << 8) | buffer[start] as u32
+                ((buffer[start] as u32) << 24) | ((buffer[start + 1] as u32) << 16) | ((buffer[start + 2] as u32) << 8) | buffer[start + 3] as u32
             },
             Endianness::Little =>
             {
-                ((buffer[start] as u32) << 24) | ((buffer[start + 1] as u32) << 16) | ((buffer[start + 2] as u32) << 8) | buffer[start + 3] as u32
+                ((buffer[start + 3] as u32) << 24) | ((buffer[start + 2] as u32) << 16) | ((buffer[start + 1] as u32) << 8) | buffer[start] as u32
             },
         }
     }
@@ -629,6 +1230,28 @@ fn get_u32(buffer : &[u8], start : usize, endianness : Endianness) -> u32
     0
 }
 
+#[inline]
+fn get_u64(buffer : &[u8], start : usize, endianness : Endianness) -> u64
+{
+    let (first, second) = (get_u32(buffer, start, endianness) as u64, get_u32(buffer, start + 4, endianness) as u64);
+    match endianness
+    {
+        Endianness::Big => (first << 32) | second,
+        Endianness::Little => (second << 32) | first
+    }
+}
+
+#[inline]
+fn set_u64(buffer : &mut Vec<u8>, data : u64, endianness : Endianness)
+{
+    let (high, low) = ((data >> 32) as u32, data as u32);
+    match endianness
+    {
+        Endianness::Big => { set_u32(buffer, high, endianness); set_u32(buffer, low, endianness); }
+        Endianness::Little => { set_u32(buffer, low, endianness); set_u32(buffer, high, endianness); }
+    }
+}
+
 #[inline]
 fn set_u32(buffer : &mut Vec<u8>, data : u32, endianness : Endianness)
 {
@@ -663,11 +1286,11 @@ fn get_u16(buffer : &[u8], start : usize, endianness : Endianness) -> u16
         {
             Endianness::Big =>
             {
-                ((buffer[start + 1] as u16) << 8) | buffer[start] as u16
+                ((buffer[start] as u16) << 8) | buffer[start + 1] as u16
             },
             Endianness::Little =>
             {
-                ((buffer[start] as u16) << 8) | buffer[start + 1] as u16
+                ((buffer[start + 1] as u16) << 8) | buffer[start] as u16
             },
         }
     }
@@ -694,4 +1317,529 @@ fn set_u16(buffer : &mut Vec<u8>, data : u16, endianness : Endianness)
         },
     }
     buffer.extend_from_slice(&bytes);
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Save the file in the format to a temporary path and load it back.
+    fn reload(file : &AudioFile, format : FileFormat, name : &str) -> AudioFile
+    {
+        let path = std::env::temp_dir().join(format!("mkaudiolibrary_{}_{}", std::process::id(), name));
+        let path = path.to_str().unwrap();
+        file.try_save(path, format).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let mut reloaded = AudioFile::default();
+        reloaded.try_load_bytes(&bytes).unwrap();
+        reloaded
+    }
+
+    /// Sine of the frequency in cycles per sample, at the amplitude.
+    fn sine(len : usize, frequency : f64, amplitude : f64) -> Vec<f64> { (0..len).map(|index| amplitude * (std::f64::consts::TAU * frequency * index as f64).sin()).collect() }
+
+    #[test]
+    fn integers_round_trip_in_both_byte_orders()
+    {
+        for endianness in [Endianness::Big, Endianness::Little]
+        {
+            let mut buffer = vec![];
+            set_u16(&mut buffer, 0x1234, endianness);
+            set_u32(&mut buffer, 0x1234_5678, endianness);
+            set_u64(&mut buffer, 0x0123_4567_89AB_CDEF, endianness);
+            assert_eq!(get_u16(&buffer, 0, endianness), 0x1234);
+            assert_eq!(get_u32(&buffer, 2, endianness), 0x1234_5678);
+            assert_eq!(get_u64(&buffer, 6, endianness), 0x0123_4567_89AB_CDEF);
+            assert_eq!(buffer[0], if endianness == Endianness::Little { 0x34 } else { 0x12 });
+        }
+    }
+
+    #[test]
+    fn reads_wav_from_external_encoder()
+    {
+        // Written by the wave module of Python: left counts up by 1000 per frame from -16000 and right is its negation.
+        let mut file = AudioFile::default();
+        file.try_load_bytes(include_bytes!("../tests/fixtures/stereo_16bit_44100.wav")).unwrap();
+        assert_eq!((file.sample_rate(), file.bit_depth(), file.num_channel(), file.num_sample()), (44100, 16, 2, 32));
+        for index in 0..32
+        {
+            let expected = (-16000.0 + 1000.0 * index as f64) / 32767.0;
+            assert_eq!((file.audio_buffer[0][index], file.audio_buffer[1][index]), (expected, -expected));
+        }
+    }
+
+    #[test]
+    fn aiff_16_bit_sine_round_trips_within_quantization()
+    {
+        let file = AudioFile { audio_buffer : vec![sine(1000, 0.01, 0.9)], bit_depth : 16, ..AudioFile::default() };
+        let reloaded = reload(&file, FileFormat::Aiff, "sine.aiff");
+        assert_eq!((reloaded.bit_depth(), reloaded.num_sample()), (16, 1000));
+        for (original, recovered) in file.audio_buffer[0].iter().zip(&reloaded.audio_buffer[0]) { assert!((original - recovered).abs() <= 0.5 / 32767.0 + 1e-12); }
+    }
+
+    #[test]
+    fn aiff_loads_with_and_without_ixml()
+    {
+        let mut file = AudioFile { audio_buffer : vec![sine(100, 0.01, 0.5)], bit_depth : 16, ..AudioFile::default() };
+        let reloaded = reload(&file, FileFormat::Aiff, "plain.aiff");
+        assert_eq!((reloaded.xml_chunk.as_str(), reloaded.num_sample()), ("", 100));
+        file.xml_chunk = "<BWFXML><PROJECT>Test</PROJECT></BWFXML>".to_string();
+        let reloaded = reload(&file, FileFormat::Aiff, "ixml.aiff");
+        assert_eq!((reloaded.xml_chunk.as_str(), reloaded.num_sample()), (file.xml_chunk.as_str(), 100));
+    }
+
+    #[test]
+    fn chunk_walker_finds_chunks_after_odd_sized_chunks()
+    {
+        // RIFF header, then fmt of 8 bits mono, odd sized bext and junk chunks with their pad bytes, odd sized data and iXML.
+        let mut buffer = b"RIFF\0\0\0\0WAVE".to_vec();
+        let chunks : [(&str, &[u8]); 5] = [("fmt ", &[1, 0, 1, 0, 0x40, 0x1F, 0, 0, 0x40, 0x1F, 0, 0, 1, 0, 8, 0]), ("bext", b"12345"), ("junk", b"abc"), ("data", &[0x80, 0xFF, 0x00]), ("iXML", b"x")];
+        let mut expected = vec![];
+        for (id, body) in chunks
+        {
+            expected.push((id, buffer.len()));
+            set_string(&mut buffer, id);
+            set_u32(&mut buffer, body.len() as u32, Endianness::Little);
+            buffer.extend_from_slice(body);
+            buffer.resize(buffer.len() + body.len() % 2, 0);
+        }
+        let size = buffer.len() as u32 - 8;
+        buffer[4..8].copy_from_slice(&size.to_le_bytes());
+
+        let found = Chunks::new(&buffer, 12, Endianness::Little).map(|index| (std::str::from_utf8(&buffer[index..index + 4]).unwrap(), index)).collect::<Vec<_>>();
+        assert_eq!(found, expected);
+        for (id, index) in expected { assert_eq!(get_index_of_chunk(&buffer, id, 12, Endianness::Little), Some(index)); }
+        assert_eq!(get_index_of_chunk(&buffer, "smpl", 12, Endianness::Little), None);
+
+        let mut file = AudioFile::default();
+        file.try_load_bytes(&buffer).unwrap();
+        assert_eq!((file.audio_buffer[0].as_slice(), file.xml_chunk.as_str()), ([0.0, 1.0, -128.0 / 127.0].as_slice(), "x"));
+    }
+
+    #[test]
+    fn chunks_after_odd_sized_sample_data_survive_saving()
+    {
+        let mut file = AudioFile { audio_buffer : vec![vec![0.0, 0.5, -0.5]], bit_depth : 8, xml_chunk : "x".to_string(), ..AudioFile::default() };
+        file.set_title("odd");
+        let wav = reload(&file, FileFormat::Wav, "odd.wav");
+        assert_eq!((wav.xml_chunk.as_str(), wav.title()), ("x", Some("odd")));
+        let aiff = reload(&file, FileFormat::Aiff, "odd.aiff");
+        assert_eq!((aiff.xml_chunk.as_str(), aiff.num_sample()), ("x", 3));
+    }
+
+    #[test]
+    fn standard_wav_headers_pass_validation()
+    {
+        // Written by the wave module of Python: frame i of channel c holds (i + 1) * (c + 1) / 16 of full scale, truncated to the step.
+        let fixtures : [(&[u8], usize, usize); 3] =
+        [
+            (include_bytes!("../tests/fixtures/mono_16bit_48000.wav"), 1, 16),
+            (include_bytes!("../tests/fixtures/stereo_16bit_48000.wav"), 2, 16),
+            (include_bytes!("../tests/fixtures/stereo_24bit_48000.wav"), 2, 24)
+        ];
+        for (bytes, num_channels, bit_depth) in fixtures
+        {
+            let mut file = AudioFile::default();
+            file.try_load_bytes(bytes).unwrap();
+            assert_eq!((file.num_channel(), file.bit_depth(), file.sample_rate(), file.num_sample()), (num_channels, bit_depth, 48000, 8));
+            for (channel, data) in file.audio_buffer.iter().enumerate()
+            {
+                for (index, sample) in data.iter().enumerate() { assert!((sample - ((index + 1) * (channel + 1)) as f64 / 16.0).abs() <= 1.0 / full_scale(bit_depth)); }
+            }
+        }
+    }
+
+    #[test]
+    fn measures_ebu_reference_tone_at_minus_23_lufs()
+    {
+        // EBU Tech 3341 case 1: 1kHz sine at -23 dBFS in both channels of a stereo file.
+        let tone = sine(48000 * 20, 1000.0 / 48000.0, db_to_ratio(-23.0));
+        let file = AudioFile { audio_buffer : vec![tone.clone(), tone], sample_rate : 48000, ..AudioFile::default() };
+        let report = file.measure_loudness();
+        assert!((report.integrated + 23.0).abs() < 0.1, "{}", report.integrated);
+        assert!((report.max_momentary + 23.0).abs() < 0.1 && (report.max_short_term + 23.0).abs() < 0.1);
+        assert!(report.loudness_range < 0.1);
+        assert!((report.true_peak + 23.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn normalize_fade_and_reverse()
+    {
+        let mut file = AudioFile { audio_buffer : vec![sine(1000, 0.01, 0.25), sine(1000, 0.013, -0.5)], ..AudioFile::default() };
+        let before = file.audio_buffer.clone();
+        file.normalize(-1.0);
+        let peak = file.audio_buffer.iter().flatten().fold(0.0f64, |peak, sample| peak.max(sample.abs()));
+        assert!((ratio_to_db(peak) + 1.0).abs() < 1e-9);
+        // One factor for every channel, here +5 dB from the peak of 0.5.
+        assert!((file.audio_buffer[0][25] / before[0][25] - file.audio_buffer[1][25] / before[1][25]).abs() < 1e-12);
+        assert!((ratio_to_db(file.audio_buffer[0][25] / before[0][25]) - 5.0206).abs() < 1e-4);
+
+        let original = file.audio_buffer.clone();
+        file.reverse();
+        assert_eq!(file.audio_buffer[0][0], original[0][999]);
+        file.reverse();
+        assert_eq!(file.audio_buffer, original);
+
+        let mut file = AudioFile { audio_buffer : vec![vec![1.0; 1000]; 2], ..AudioFile::default() };
+        file.fade_in(100);
+        file.fade_out(100);
+        for channel in &file.audio_buffer
+        {
+            assert_eq!((channel[0], channel[999]), (0.0, 0.0));
+            assert!(channel[..100].windows(2).all(|pair| pair[0] < pair[1]) && channel[900..].windows(2).all(|pair| pair[0] > pair[1]));
+            assert!(channel[100..900].iter().all(|&sample| sample == 1.0));
+            // Equal power at the midpoint of the fade.
+            assert!((channel[50] - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn mono_mixdown_extraction_and_append()
+    {
+        let tone = sine(1000, 0.01, 0.5);
+        let mut file = AudioFile { audio_buffer : vec![tone.clone(), tone.iter().map(|sample| -sample).collect()], sample_rate : 48000, bit_depth : 24, ..AudioFile::default() };
+        for pan_law in [PanLaw::Minus3dB, PanLaw::Minus6dB]
+        {
+            let mono = file.to_mono(pan_law);
+            assert_eq!((mono.num_channel(), mono.num_sample(), mono.sample_rate(), mono.bit_depth()), (1, 1000, 48000, 24));
+            assert!(mono.audio_buffer[0].iter().all(|sample| sample.abs() < 1e-15));
+        }
+        assert_eq!(file.extract_channel(1).unwrap().audio_buffer[0][25], -0.5);
+        assert!(file.extract_channel(2).is_none());
+
+        let other = AudioFile { audio_buffer : vec![vec![0.25; 300]; 2], sample_rate : 48000, ..AudioFile::default() };
+        file.append(&other).unwrap();
+        assert_eq!((file.num_channel(), file.num_sample()), (2, 1300));
+        assert_eq!((file.audio_buffer[0][999], file.audio_buffer[1][1000]), (tone[999], 0.25));
+        let mono = AudioFile { audio_buffer : vec![vec![0.0; 10]], sample_rate : 48000, ..AudioFile::default() };
+        assert!(matches!(file.append(&mono), Err(AudioFileError::ChannelMismatch(2, 1))));
+        let other = AudioFile { sample_rate : 44100, ..other };
+        assert!(matches!(file.append(&other), Err(AudioFileError::SampleRateMismatch(48000, 44100))));
+        assert_eq!(file.num_sample(), 1300);
+    }
+
+    #[test]
+    fn triangular_dither_decorrelates_8_bit_error()
+    {
+        // Normalized autocorrelation of the quantization error at a lag of one sample.
+        let correlation = |dither : Dither|
+        {
+            let tone = sine(48000, 0.0037, 3.3 / 127.0);
+            let file = AudioFile { audio_buffer : vec![tone.clone()], bit_depth : 8, dither, ..AudioFile::default() };
+            let reloaded = reload(&file, FileFormat::Wav, &format!("dither_{}.wav", dither as u8));
+            let error : Vec<f64> = reloaded.audio_buffer[0].iter().zip(&tone).map(|(quantized, original)| quantized - original).collect();
+            let mean = error.iter().sum::<f64>() / error.len() as f64;
+            let power = error.iter().map(|error| (error - mean).powi(2)).sum::<f64>();
+            error.windows(2).map(|pair| (pair[0] - mean) * (pair[1] - mean)).sum::<f64>() / power
+        };
+        let undithered = correlation(Dither::None);
+        let dithered = correlation(Dither::Triangular);
+        assert!(undithered > 0.5, "{undithered}");
+        assert!(dithered.abs() < 0.02, "{dithered}");
+    }
+
+    #[test]
+    fn streaming_in_blocks_matches_loading()
+    {
+        let file = AudioFile { audio_buffer : vec![sine(5000, 0.01, 0.9), sine(5000, 0.007, -0.6)], bit_depth : 24, sample_rate : 48000, ..AudioFile::default() };
+        let path = std::env::temp_dir().join(format!("mkaudiolibrary_{}_stream.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        file.try_save(path, FileFormat::Wav).unwrap();
+        let mut loaded = AudioFile::default();
+        loaded.try_load(path).unwrap();
+        let mut reader = WavStreamReader::open(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!((reader.num_channel(), reader.sample_rate(), reader.bit_depth(), reader.num_frames()), (2, 48000, 24, 5000));
+        let mut streamed = vec![vec![]; 2];
+        let mut blocks = 0;
+        while let Some(block) = reader.read_block(1024)
+        {
+            for (channel, buffer) in streamed.iter_mut().zip(&block) { channel.extend(buffer.to_vec()); }
+            blocks += 1;
+        }
+        assert_eq!((blocks, reader.position()), (5, 5000));
+        assert_eq!(streamed, loaded.audio_buffer);
+
+        reader.seek(4000);
+        assert_eq!(reader.read_block(1024).unwrap()[1].to_vec(), loaded.audio_buffer[1][4000..].to_vec());
+    }
+
+    #[test]
+    fn rf64_sizes_resolve_from_ds64()
+    {
+        let file = AudioFile { audio_buffer : vec![(0..10).map(|index| index as f64 * 1000.0 / 32767.0).collect()], bit_depth : 16, ..AudioFile::default() };
+        let path = std::env::temp_dir().join(format!("mkaudiolibrary_{}_forced.rf64", std::process::id()));
+        let path = path.to_str().unwrap();
+        file.save_rf64(path);
+        let mut bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // ds64 comes first and holds the RIFF size, data size and sample count, leaving sentinels in the RIFF and data sizes.
+        assert_eq!((&bytes[0..4], &bytes[12..16]), (&b"RF64"[..], &b"ds64"[..]));
+        assert_eq!(get_u32(&bytes, 4, Endianness::Little), u32::MAX);
+        assert_eq!(get_u64(&bytes, 20, Endianness::Little), bytes.len() as u64 - 8);
+        assert_eq!((get_u64(&bytes, 28, Endianness::Little), get_u64(&bytes, 36, Endianness::Little)), (20, 10));
+        let data = get_index_of_chunk(&bytes, "data", 12, Endianness::Little).unwrap();
+        assert_eq!((get_u32(&bytes, data + 4, Endianness::Little), bytes.len() - data - 8), (u32::MAX, 20));
+
+        let mut loaded = AudioFile::default();
+        loaded.try_load_bytes(&bytes).unwrap();
+        assert_eq!(loaded.audio_buffer, file.audio_buffer);
+
+        // A smaller data size in ds64 wins over the bytes that follow the data chunk.
+        bytes[28..36].copy_from_slice(&16u64.to_le_bytes());
+        loaded.try_load_bytes(&bytes).unwrap();
+        assert_eq!(loaded.audio_buffer[0], file.audio_buffer[0][..8].to_vec());
+    }
+
+    #[test]
+    fn smpl_loops_round_trip()
+    {
+        let mut file = AudioFile { audio_buffer : vec![sine(1000, 0.01, 0.5)], midi_unity_note : 69, midi_pitch_fraction : 0x8000_0000, ..AudioFile::default() };
+        file.add_loop(SampleLoop { start : 100, end : 499, loop_type : LoopType::Forward });
+        file.add_loop(SampleLoop { start : 500, end : 899, loop_type : LoopType::PingPong });
+        let reloaded = reload(&file, FileFormat::Wav, "loops.wav");
+        assert_eq!(reloaded.loops(), file.loops());
+        assert_eq!((reloaded.midi_unity_note, reloaded.midi_pitch_fraction), (69, 0x8000_0000));
+        assert_eq!(reloaded.num_sample(), 1000);
+
+        file.clear_loops();
+        assert!(reload(&file, FileFormat::Wav, "no_loops.wav").loops().is_empty());
+    }
+
+    #[test]
+    fn info_tags_round_trip()
+    {
+        let mut file = AudioFile { audio_buffer : vec![vec![0.0; 10]], ..AudioFile::default() };
+        file.set_title("Odd");
+        file.set_artist("Artist");
+        let reloaded = reload(&file, FileFormat::Wav, "info.wav");
+        assert_eq!((reloaded.title(), reloaded.artist(), reloaded.comment()), (Some("Odd"), Some("Artist"), None));
+        assert_eq!(reloaded.num_sample(), 10);
+    }
+
+    #[test]
+    fn reads_six_channel_extensible_fixtures()
+    {
+        // Written by hand with the PCM and IEEE float SubFormat GUIDs and the 5.1 channel mask of 0x3F.
+        let mut file = AudioFile::default();
+        file.try_load_bytes(include_bytes!("../tests/fixtures/surround_24bit_extensible.wav")).unwrap();
+        assert_eq!((file.num_channel(), file.num_sample(), file.bit_depth(), file.channel_mask()), (6, 8, 24, Some(0x3F)));
+        for (channel, data) in file.audio_buffer.iter().enumerate()
+        {
+            for (index, sample) in data.iter().enumerate() { assert_eq!(*sample, ((index + 1) * (channel + 1) * 100000) as f64 / 8388607.0); }
+        }
+
+        file.try_load_bytes(include_bytes!("../tests/fixtures/surround_float_extensible.wav")).unwrap();
+        assert_eq!((file.num_channel(), file.num_sample(), file.bit_depth(), file.channel_mask()), (6, 8, 32, Some(0x3F)));
+        for (channel, data) in file.audio_buffer.iter().enumerate()
+        {
+            for (index, sample) in data.iter().enumerate() { assert_eq!(*sample, -(((index + 1) * (channel + 1)) as f64) / 64.0); }
+        }
+
+        file.try_load_bytes(include_bytes!("../tests/fixtures/stereo_16bit_48000.wav")).unwrap();
+        assert_eq!(file.channel_mask(), None);
+    }
+
+    #[test]
+    fn companded_wav_round_trips_on_the_g711_curves()
+    {
+        // Every code decodes to a level that encodes back to it, except negative zero of mu-law.
+        for byte in 0..=255u8
+        {
+            assert_eq!(linear_to_alaw(alaw_to_linear(byte)), byte);
+            if byte != 0x7F { assert_eq!(linear_to_mulaw(mulaw_to_linear(byte)), byte); }
+        }
+
+        let ramp : Vec<f64> = (-1000..=1000).map(|index| index as f64 / 1000.0).chain((-64..=64).map(|index| index as f64 / 32767.0)).collect();
+        for (companding, finest_step) in [(Companding::ALaw, 16.0), (Companding::MuLaw, 8.0)]
+        {
+            let file = AudioFile { audio_buffer : vec![ramp.clone()], companding, ..AudioFile::default() };
+            let reloaded = reload(&file, FileFormat::Wav, &format!("companded_{}.wav", companding as u8));
+            assert_eq!((reloaded.get_companding(), reloaded.bit_depth()), (companding, 8));
+            for (original, recovered) in ramp.iter().zip(&reloaded.audio_buffer[0])
+            {
+                let error = (original - recovered).abs();
+                // Steps near zero are as fine as a few 16 bits steps, and grow with the level to about 1/16 of it, where the bias of mu-law moves the segments slightly.
+                // Rounding to 16 bits before encoding adds up to a 16 bits step.
+                assert!(error <= (finest_step / 2.0 / 32767.0f64).max(original.abs() / 30.0) + 1.0 / 32767.0, "{original} {recovered}");
+            }
+            // Full scale falls on the coarsest step, below 1.0.
+            let full_scale = reloaded.audio_buffer[0][2000];
+            assert!(full_scale > 0.98 && full_scale < 1.0);
+        }
+    }
+
+    #[cfg(feature = "flac")]
+    #[test]
+    fn flac_fixture_decodes_to_the_same_samples_as_wav()
+    {
+        // Written by hand as a single frame of verbatim subframes, holding the samples of the 16 bits stereo WAV fixture.
+        let mut flac = AudioFile::default();
+        flac.try_load_bytes(include_bytes!("../tests/fixtures/stereo_16bit_48000.flac")).unwrap();
+        let mut wav = AudioFile::default();
+        wav.try_load_bytes(include_bytes!("../tests/fixtures/stereo_16bit_48000.wav")).unwrap();
+        assert_eq!((flac.sample_rate(), flac.bit_depth(), flac.num_channel(), flac.num_sample()), (48000, 16, 2, 8));
+        assert_eq!(flac.audio_buffer, wav.audio_buffer);
+    }
+
+    #[test]
+    fn resampling_keeps_pitch_both_ways()
+    {
+        let mut file = AudioFile { audio_buffer : vec![sine(44100, 1000.0 / 44100.0, 0.5)], sample_rate : 44100, ..AudioFile::default() };
+        file.resample(48000);
+        assert_eq!((file.sample_rate(), file.num_sample()), (48000, 48000));
+        // Away from the edges, where the filter sees silence around the file, the output is the same tone sampled at 48kHz.
+        let expected = sine(48000, 1000.0 / 48000.0, 0.5);
+        let error = file.audio_buffer[0][1000..47000].iter().zip(&expected[1000..47000]).fold(0.0f64, |error, (data, expected)| error.max((data - expected).abs()));
+        assert!(error < 1e-5, "{error}");
+
+        file.resample(44100);
+        assert_eq!((file.sample_rate(), file.num_sample()), (44100, 44100));
+        let expected = sine(44100, 1000.0 / 44100.0, 0.5);
+        let error = file.audio_buffer[0][1000..43000].iter().zip(&expected[1000..43000]).fold(0.0f64, |error, (data, expected)| error.max((data - expected).abs()));
+        assert!(error < 1e-5, "{error}");
+    }
+
+    #[test]
+    fn interleaved_round_trips_for_mono_and_stereo()
+    {
+        let mut file = AudioFile::default();
+        file.from_interleaved_f32(&[0.5, -0.25, 0.125], 1);
+        assert_eq!(file.audio_buffer, vec![vec![0.5, -0.25, 0.125]]);
+        assert_eq!(file.to_interleaved_f32(), vec![0.5, -0.25, 0.125]);
+
+        // Trailing sample of an incomplete frame is dropped.
+        file.from_interleaved_f32(&[0.5, -0.5, 0.25, -0.25, 1.0], 2);
+        assert_eq!(file.audio_buffer, vec![vec![0.5, 0.25], vec![-0.5, -0.25]]);
+        assert_eq!(file.to_interleaved_f32(), vec![0.5, -0.5, 0.25, -0.25]);
+
+        let stereo = [0.1, -0.2, 0.3, -0.4, 0.5, -0.6];
+        file.from_interleaved_f64(&stereo, 2);
+        assert_eq!((file.num_channel(), file.num_sample(), file.to_interleaved_f64()), (2, 3, stereo.to_vec()));
+        file.from_interleaved_f64(&stereo, 0);
+        assert_eq!(file.num_channel(), 0);
+    }
+
+    #[test]
+    fn fine_steps_round_trip_at_24_bits_without_bias()
+    {
+        let ramp : Vec<f64> = (-100_000..=100_000).map(|index| index as f64 / 100_000.0).collect();
+        let file = AudioFile { audio_buffer : vec![ramp.clone()], bit_depth : 24, ..AudioFile::default() };
+        for format in [FileFormat::Wav, FileFormat::Aiff]
+        {
+            let reloaded = reload(&file, format, if format == FileFormat::Wav { "fine.wav" } else { "fine.aiff" });
+            let errors : Vec<f64> = ramp.iter().zip(&reloaded.audio_buffer[0]).map(|(original, recovered)| (recovered - original) * 8388607.0).collect();
+            // Rounding keeps every sample within half a step, and the errors average out.
+            assert!(errors.iter().all(|error| error.abs() <= 0.5 + 1e-6));
+            assert!((errors.iter().sum::<f64>() / errors.len() as f64).abs() < 0.01);
+            assert_eq!((reloaded.audio_buffer[0][0], reloaded.audio_buffer[0][200_000]), (-1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn errors_name_what_went_wrong()
+    {
+        let mut file = AudioFile::default();
+        let missing = std::env::temp_dir().join(format!("mkaudiolibrary_{}_missing.wav", std::process::id()));
+        assert!(matches!(file.try_load(missing.to_str().unwrap()), Err(AudioFileError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound));
+        let wav = include_bytes!("../tests/fixtures/stereo_16bit_48000.wav");
+        assert!(matches!(file.try_load_bytes(&wav[..30]), Err(AudioFileError::Corrupt(_))));
+        assert!(matches!(file.try_load_bytes(b"OggS and more bytes than a header"), Err(AudioFileError::UnknownFormat)));
+
+        // 12 bits samples load in 2 bytes, while 40 bits samples are rejected.
+        let twelve_bits = AudioFile { audio_buffer : vec![vec![0.5, -0.25, 1.0 / 2047.0]], bit_depth : 12, ..AudioFile::default() };
+        let reloaded = reload(&twelve_bits, FileFormat::Wav, "twelve_bits.wav");
+        assert_eq!((reloaded.bit_depth(), reloaded.audio_buffer[0][2]), (12, 1.0 / 2047.0));
+        let mut wav = wav.to_vec();
+        wav[34..36].copy_from_slice(&40u16.to_le_bytes());
+        assert!(matches!(file.try_load_bytes(&wav), Err(AudioFileError::UnsupportedBitDepth(40))));
+        let path = missing.to_str().unwrap();
+        assert!(matches!(AudioFile { bit_depth : 40, ..twelve_bits }.try_save(path, FileFormat::Wav), Err(AudioFileError::UnsupportedBitDepth(40))));
+        assert!(matches!(file.try_save(path, FileFormat::Flac), Err(AudioFileError::UnknownFormat)));
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn malformed_buffers_return_errors()
+    {
+        let mut file = AudioFile::default();
+        assert!(file.try_load_bytes(&[]).is_err());
+        assert!(file.try_load_bytes(b"RIFF").is_err());
+        assert!(file.try_load_bytes(b"FORM").is_err());
+        // Headers up to the start of the sample data, with data sizes that point past the end.
+        let wav = include_bytes!("../tests/fixtures/stereo_16bit_48000.wav");
+        assert!(matches!(file.try_load_bytes(&wav[..44]), Err(AudioFileError::Corrupt(_))));
+        let path = std::env::temp_dir().join(format!("mkaudiolibrary_{}_header.aiff", std::process::id()));
+        AudioFile { audio_buffer : vec![vec![0.5; 8]], ..AudioFile::default() }.try_save(path.to_str().unwrap(), FileFormat::Aiff).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let data = get_index_of_chunk(&bytes, "SSND", 12, Endianness::Big).unwrap();
+        for len in [12, 20, data, data + 16] { assert!(file.try_load_bytes(&bytes[..len]).is_err(), "{len}"); }
+        // Every truncation of a valid file returns instead of panicking.
+        for len in 0..wav.len() { let _ = file.try_load_bytes(&wav[..len]); }
+        for len in 0..bytes.len() { let _ = file.try_load_bytes(&bytes[..len]); }
+    }
+
+
+    #[test]
+    fn clip_detection_reports_the_earliest_over_across_channels()
+    {
+        let mut file = AudioFile { audio_buffer : vec![sine(100, 0.01, 0.9), sine(100, 0.02, 0.9)], ..AudioFile::default() };
+        assert_eq!(file.count_clipped_samples(1.0), 0);
+        assert_eq!(file.first_clip_position(1.0), None);
+
+        (file.audio_buffer[0][60], file.audio_buffer[1][30], file.audio_buffer[1][60]) = (1.1, -1.2, 1.3);
+        assert_eq!(file.count_clipped_samples(1.0), 3);
+        assert_eq!(file.first_clip_position(1.0), Some((1, 30)));
+        // Overs at the same sample are reported on the lowest channel.
+        file.audio_buffer[1][30] = 0.0;
+        assert_eq!(file.first_clip_position(1.0), Some((0, 60)));
+    }
+
+
+    #[test]
+    fn from_buffers_resize_takes_the_shape_of_the_buffers()
+    {
+        let buffers = |lengths : &[usize]| lengths.iter().map(|&len|
+        {
+            let mut buffer = Buffer::<f64>::new(len);
+            buffer.lock();
+            buffer.copy_from_slice(&sine(len, 0.01, 0.5));
+            buffer.unlock();
+            buffer
+        }).collect::<Vec<Buffer<f64>>>();
+        let mut file = AudioFile { audio_buffer : vec![sine(100, 0.02, 0.25); 2], ..AudioFile::default() };
+
+        // Mismatched lengths leave the file as it was.
+        let original = file.audio_buffer.clone();
+        assert!(matches!(file.from_buffers_resize(&buffers(&[150, 120])), Err(AudioFileError::LengthMismatch(150, 120))));
+        assert!(matches!(file.from_buffers(&buffers(&[50, 60]), 0), Err(AudioFileError::LengthMismatch(50, 60))));
+        assert_eq!(file.audio_buffer, original);
+
+        file.from_buffers_resize(&buffers(&[150, 150, 150])).unwrap();
+        assert_eq!((file.num_channel(), file.num_sample()), (3, 150));
+        assert!(file.audio_buffer.iter().all(|channel| *channel == sine(150, 0.01, 0.5)));
+    }
+
+
+    #[test]
+    fn mono_compatibility_follows_the_phase_of_the_channels()
+    {
+        let tone = sine(4800, 0.01, 0.5);
+        let mut file = AudioFile { audio_buffer : vec![tone.clone(), tone.clone()], ..AudioFile::default() };
+        assert!((file.mono_compatibility() - 1.0).abs() < 1e-12);
+        file.invert_phase(1);
+        assert!(file.mono_compatibility() < 1e-12);
+        file.invert_phase(1);
+        assert!((file.mono_compatibility() - 1.0).abs() < 1e-12);
+
+        // Uncorrelated channels lose half the energy, and a single channel has nothing to cancel.
+        let uncorrelated = AudioFile { audio_buffer : vec![sine(4800, 0.01, 0.5), sine(4800, 0.0125, 0.5)], ..AudioFile::default() };
+        assert!((uncorrelated.mono_compatibility() - 0.5).abs() < 1e-3);
+        assert_eq!(AudioFile { audio_buffer : vec![tone], ..AudioFile::default() }.mono_compatibility(), 1.0);
+    }
+}
@@ -0,0 +1,148 @@
+/// Byte order of PCM samples, little for WAV and big for AIFF.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endianness
+{
+    /// Most significant byte first.
+    Big,
+    /// Least significant byte first.
+    Little
+}
+
+/// Integer value of full scale at the bit depth, the maximum of the signed integer of the bit depth, and at least 1.
+/// The minimum of the signed integer reads slightly below -1.0, as in 16 bits where -32768 reads as -32768 / 32767.
+pub fn full_scale(bit_depth : usize) -> f64 { ((1u64 << (bit_depth.clamp(1, 32) - 1)) - 1).max(1) as f64 }
+
+/// Read PCM sample of 1 to 32 bits from the start of the bytes, normalized from -1.0 to 1.0. The sample is left-justified in whole bytes as both WAV and AIFF store it.
+pub fn from_pcm_bytes(bytes : &[u8], bit_depth : usize, endianness : Endianness) -> f64
+{
+    let bit_depth = bit_depth.clamp(1, 32);
+    let num_bytes = bit_depth.div_ceil(8);
+    let mut sample = 0u32;
+    for index in 0..num_bytes
+    {
+        let byte = match endianness
+        {
+            Endianness::Big => bytes[index],
+            Endianness::Little => bytes[num_bytes - 1 - index]
+        };
+        sample = (sample << 8) | byte as u32;
+    }
+    // Move the most significant bit to bit 31 so the sign extends from the container, then drop the padding bits.
+    let sample = (sample << (32 - 8 * num_bytes)).cast_signed() >> (32 - bit_depth);
+    sample as f64 / full_scale(bit_depth)
+}
+
+/// Read WAV PCM sample of 1 to 32 bits, normalized from -1.0 to 1.0. WAV stores samples of 8 bits or fewer as unsigned with an offset of half the range,
+/// so the top bit is flipped to the signed form before decoding. Wider samples are signed little endian.
+pub fn from_wav_bytes(bytes : &[u8], bit_depth : usize) -> f64
+{
+    if bit_depth > 8 { return from_pcm_bytes(bytes, bit_depth, Endianness::Little) }
+    from_pcm_bytes(&[bytes[0] ^ 0x80], bit_depth, Endianness::Little)
+}
+
+/// Append PCM sample of 1 to 32 bits to the output, rounded to the nearest step and clamped to the range of the bit depth.
+pub fn to_pcm_bytes(sample : f64, bit_depth : usize, endianness : Endianness, out : &mut Vec<u8>)
+{
+    let bit_depth = bit_depth.clamp(1, 32);
+    let num_bytes = bit_depth.div_ceil(8);
+    let min = -(1i64 << (bit_depth - 1));
+    let sample = ((sample * full_scale(bit_depth)).round() as i64).clamp(min, -min - 1) as u32;
+    let sample = sample << (8 * num_bytes - bit_depth);
+    for index in 0..num_bytes
+    {
+        let shift = match endianness
+        {
+            Endianness::Big => 8 * (num_bytes - 1 - index),
+            Endianness::Little => 8 * index
+        };
+        out.push((sample >> shift) as u8);
+    }
+}
+
+/// Append WAV PCM sample of 1 to 32 bits, rounded to the nearest step and clamped to the range of the bit depth.
+/// Samples of 8 bits or fewer are written unsigned with an offset of half the range, as WAV stores them. Wider samples are signed little endian.
+pub fn to_wav_bytes(sample : f64, bit_depth : usize, out : &mut Vec<u8>)
+{
+    to_pcm_bytes(sample, bit_depth, Endianness::Little, out);
+    if bit_depth <= 8 { if let Some(byte) = out.last_mut() { *byte ^= 0x80; } }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Signed integer codes of the bit depth, every one up to 16 bits and an even spread with both ends and zero above.
+    fn codes(bit_depth : usize) -> Vec<i64>
+    {
+        let (min, max) = (-(1i64 << (bit_depth - 1)), (1i64 << (bit_depth - 1)) - 1);
+        let step = 1i64 << bit_depth.saturating_sub(16);
+        let mut codes = (min..=max).step_by(step as usize).collect::<Vec<i64>>();
+        codes.extend([min + 1, -1, 0, 1, max - 1, max].into_iter().filter(|code| (min..=max).contains(code)));
+        codes
+    }
+
+    /// Bytes of the code left-justified in whole bytes, in the byte order.
+    fn packed(code : i64, bit_depth : usize, endianness : Endianness) -> Vec<u8>
+    {
+        let num_bytes = bit_depth.div_ceil(8);
+        let bytes = ((code as u32) << (8 * num_bytes - bit_depth)).to_be_bytes();
+        let mut bytes = bytes[4 - num_bytes..].to_vec();
+        if endianness == Endianness::Little { bytes.reverse(); }
+        bytes
+    }
+
+    #[test]
+    fn pcm_round_trips_every_code_at_every_bit_depth()
+    {
+        for bit_depth in 1..=32
+        {
+            for endianness in [Endianness::Big, Endianness::Little]
+            {
+                for code in codes(bit_depth)
+                {
+                    let bytes = packed(code, bit_depth, endianness);
+                    let sample = from_pcm_bytes(&bytes, bit_depth, endianness);
+                    assert_eq!(sample, code as f64 / full_scale(bit_depth), "{bit_depth} {endianness:?} {code}");
+                    let mut out = Vec::new();
+                    to_pcm_bytes(sample, bit_depth, endianness, &mut out);
+                    assert_eq!(out, bytes, "{bit_depth} {endianness:?} {code}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pcm_clamps_beyond_full_scale()
+    {
+        for bit_depth in [1, 8, 16, 24, 32]
+        {
+            let (mut high, mut low) = (Vec::new(), Vec::new());
+            to_pcm_bytes(1.5, bit_depth, Endianness::Big, &mut high);
+            to_pcm_bytes(-1.5, bit_depth, Endianness::Big, &mut low);
+            assert_eq!(high, packed((1i64 << (bit_depth - 1)) - 1, bit_depth, Endianness::Big));
+            assert_eq!(low, packed(-(1i64 << (bit_depth - 1)), bit_depth, Endianness::Big));
+        }
+    }
+
+    #[test]
+    fn wav_stores_8_bits_or_fewer_unsigned()
+    {
+        assert_eq!(from_wav_bytes(&[0x80], 8), 0.0);
+        assert_eq!(from_wav_bytes(&[0xFF], 8), 1.0);
+        assert_eq!(from_wav_bytes(&[0x00], 8), -128.0 / 127.0);
+        for bit_depth in 1..=32
+        {
+            for code in codes(bit_depth)
+            {
+                let mut bytes = packed(code, bit_depth, Endianness::Little);
+                if bit_depth <= 8 { bytes[0] ^= 0x80; }
+                let sample = from_wav_bytes(&bytes, bit_depth);
+                assert_eq!(sample, code as f64 / full_scale(bit_depth), "{bit_depth} {code}");
+                let mut out = Vec::new();
+                to_wav_bytes(sample, bit_depth, &mut out);
+                assert_eq!(out, bytes, "{bit_depth} {code}");
+            }
+        }
+    }
+}
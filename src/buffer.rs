@@ -1,4 +1,5 @@
 use std::alloc::{alloc_zeroed, dealloc, Layout, LayoutError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// Sized Buffer that can be used in multi-threaded environment, with reference count and lock.
 pub struct Buffer<T : Clone + Default + Send + Sync>
@@ -7,7 +8,7 @@ pub struct Buffer<T : Clone + Default + Send + Sync>
     len : *mut usize,
     lock : *mut bool,
     locked_here : bool,
-    count : *mut usize,
+    count : *mut AtomicUsize,
     default : T
 }
 impl<T : Clone + Default + Send + Sync> Buffer<T>
@@ -18,19 +19,23 @@ impl<T : Clone + Default + Send + Sync> Buffer<T>
         unsafe
         {
             let array_layout = std::alloc::Layout::array::<T>(len).unwrap();
+            let length = std::alloc::alloc_zeroed(std::alloc::Layout::new::<usize>()) as * mut usize;
+            *length = len;
             Self
             {
                 element : std::alloc::alloc_zeroed(array_layout) as * mut T,
-                len : std::alloc::alloc_zeroed(std::alloc::Layout::new::<usize>()) as * mut usize,
+                len : length,
                 lock : std::alloc::alloc_zeroed(std::alloc::Layout::new::<bool>()) as * mut bool,
                 locked_here : false,
-                count : std::alloc::alloc_zeroed(std::alloc::Layout::new::<usize>()) as * mut usize,
+                count : Box::into_raw(Box::new(AtomicUsize::new(1))),
                 default : T::default()
             }
         }
     }
     /// Get length of the Buffer.
     pub fn len(&self) -> usize { unsafe { *self.len } }
+    /// Get number of live Buffers sharing this data, including this one.
+    pub fn ref_count(&self) -> usize { unsafe { (*self.count).load(Ordering::Acquire) } }
     /// resize the Buffer.
     pub fn resize(&mut self, new_len : usize)
     {
@@ -39,6 +44,7 @@ impl<T : Clone + Default + Send + Sync> Buffer<T>
         unsafe
         {
             let element = std::alloc::alloc_zeroed(alloc_layout) as * mut T;
+            if new_len < self.len() && std::mem::needs_drop::<T>() { std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(self.element.add(new_len), self.len() - new_len)); }
             std::ptr::copy_nonoverlapping(self.element, element, std::cmp::min(self.len(), new_len));
             std::alloc::dealloc(self.element as * mut u8, dealloc_layout);
             self.element = element;
@@ -46,7 +52,12 @@ impl<T : Clone + Default + Send + Sync> Buffer<T>
         }
     }
     /// New Buffer from raw pointer.
-    pub fn from_raw(ptr : * mut T, length : usize) -> Self
+    ///
+    /// # Safety
+    /// The Buffer takes ownership of the memory: `ptr` must point to `length` initialised elements allocated by the global allocator
+    /// with `Layout::array::<T>(length)`, and nothing else may free or resize it. Memory owned elsewhere, such as a `Vec`, must be
+    /// released from its owner first (e.g. through `ManuallyDrop`), otherwise it is freed twice when the Buffer drops.
+    pub unsafe fn from_raw(ptr : * mut T, length : usize) -> Self
     {
         unsafe
         {
@@ -54,7 +65,7 @@ impl<T : Clone + Default + Send + Sync> Buffer<T>
             *len = length;
             let lock = std::alloc::alloc_zeroed(std::alloc::Layout::new::<bool>()) as *mut bool;
             *lock = false;
-            Self { element : ptr, len, lock, locked_here : false, count : std::alloc::alloc_zeroed(std::alloc::Layout::new::<usize>()) as *mut usize, default : T::default() }
+            Self { element : ptr, len, lock, locked_here : false, count : Box::into_raw(Box::new(AtomicUsize::new(1))), default : T::default() }
         }
     }
     /// Try to lock in time. True if success and false if failed.
@@ -94,6 +105,95 @@ impl<T : Clone + Default + Send + Sync> Buffer<T>
         }
     }
 }
+impl<T : Clone + Default + Send + Sync> Buffer<Buffer<T>>
+{
+    /// New Buffer of channels, each a Buffer of the buffer size. Neither the channels nor the Buffer are locked.
+    pub fn channels(channels : usize, buffer_size : usize) -> Self
+    {
+        let mut buffers = Self::new(channels);
+        buffers.lock();
+        for channel in 0..channels { buffers[channel] = Buffer::new(buffer_size); }
+        buffers.unlock();
+        buffers
+    }
+}
+impl<T : crate::dsp::Sample> Buffer<T>
+{
+    /// Replace NaN and infinity with 0.0. Returns the count of replaced samples.
+    pub fn sanitize(&mut self) -> usize
+    {
+        let locked = self.locked_here;
+        if !locked { self.lock(); }
+        let mut count = 0;
+        for sample in self.as_mut().iter_mut().filter(|sample| !sample.is_finite())
+        {
+            *sample = T::from_f64(0.0);
+            count += 1;
+        }
+        if !locked { self.unlock(); }
+        count
+    }
+}
+impl Buffer<f64>
+{
+    /// Highest absolute value. 0.0 if empty.
+    pub fn peak(&self) -> f64 { self.find_peak_sample().1.abs() }
+    /// Root mean square. 0.0 if empty.
+    pub fn rms(&self) -> f64
+    {
+        if self.is_empty() { return 0.0 }
+        (self.as_ref().iter().map(|sample| sample * sample).sum::<f64>() / self.len() as f64).sqrt()
+    }
+    /// Highest absolute value in dB. Negative infinity if empty or silent.
+    pub fn peak_db(&self) -> f64 { crate::dsp::ratio_to_db(self.peak()) }
+    /// Root mean square in dB. Negative infinity if empty or silent.
+    pub fn rms_db(&self) -> f64 { crate::dsp::ratio_to_db(self.rms()) }
+    /// Highest absolute value reconstructed between samples with 4x oversampling after ITU-R BS.1770. 0.0 if empty.
+    pub fn true_peak(&self) -> f64
+    {
+        let mut detector = crate::dsp::TruePeakDetector::default();
+        // Zeros as long as the filter flush the reconstruction around the last samples.
+        let peak = self.as_ref().iter().chain([0.0; 12].iter()).fold(0.0f64, |peak, sample| peak.max(detector.process(*sample)));
+        peak.max(self.peak())
+    }
+    /// Highest reconstructed absolute value in dBTP. Negative infinity if empty or silent.
+    pub fn true_peak_db(&self) -> f64 { crate::dsp::ratio_to_db(self.true_peak()) }
+    /// Number of samples whose absolute value exceeds the threshold, such as 1.0 for full scale.
+    pub fn count_clipped_samples(&self, threshold : f64) -> usize { self.as_ref().iter().filter(|sample| sample.abs() > threshold).count() }
+    /// Index of the first sample whose absolute value exceeds the threshold.
+    pub fn first_clip_position(&self, threshold : f64) -> Option<usize> { self.as_ref().iter().position(|sample| sample.abs() > threshold) }
+    /// Index and value of the sample with the highest absolute value. (0, 0.0) if empty.
+    pub fn find_peak_sample(&self) -> (usize, f64)
+    {
+        if self.is_empty() { return (0, 0.0) }
+        self.as_ref().iter().copied().enumerate().fold((0, 0.0), |peak, (index, sample)| if sample.abs() > peak.1.abs() { (index, sample) } else { peak })
+    }
+    /// Multiply every sample by gain.
+    pub fn mul_scalar(&mut self, gain : f64)
+    {
+        let locked = self.locked_here;
+        if !locked { self.lock(); }
+        self.as_mut().iter_mut().for_each(|sample| *sample *= gain);
+        if !locked { self.unlock(); }
+    }
+    /// Add other scaled by other_gain, over the shorter of both lengths.
+    pub fn mix(&mut self, other : &Buffer<f64>, other_gain : f64) { self.combine(other, |sample, other| sample + other * other_gain); }
+    /// Copy samples of other, over the shorter of both lengths.
+    pub fn copy_from(&mut self, other : &Buffer<f64>) { self.combine(other, |_, other| other); }
+    #[inline]
+    fn combine(&mut self, other : &Buffer<f64>, function : impl Fn(f64, f64) -> f64)
+    {
+        let locked = self.locked_here;
+        if !locked { self.lock(); }
+        for (sample, other) in self.as_mut().iter_mut().zip(other.as_ref()) { *sample = function(*sample, *other); }
+        if !locked { self.unlock(); }
+    }
+}
+/// Add other, over the shorter of both lengths.
+impl std::ops::AddAssign<&Buffer<f64>> for Buffer<f64>
+{
+    fn add_assign(&mut self, other : &Buffer<f64>) { self.combine(other, |sample, other| sample + other); }
+}
 impl<T : Clone + Default + Send + Sync> std::ops::Index<usize> for Buffer<T>
 {
     type Output = T;
@@ -146,7 +246,7 @@ impl<T : Clone + Default + Send + Sync> Clone for Buffer<T>
     {
         unsafe 
         {
-            *self.count += 1;
+            (*self.count).fetch_add(1, Ordering::Relaxed);
             Self
             {
                 element : self.element,
@@ -173,7 +273,7 @@ impl<T : Clone + Default + Send + Sync> Default for Buffer<T>
                 len : std::alloc::alloc_zeroed(std::alloc::Layout::new::<usize>()) as * mut usize,
                 lock,
                 locked_here : false,
-                count : std::alloc::alloc_zeroed(std::alloc::Layout::new::<usize>()) as * mut usize,
+                count : Box::into_raw(Box::new(AtomicUsize::new(1))),
                 default : T::default()
             }
         }
@@ -188,26 +288,35 @@ impl<T : Clone + Default + Send + Sync> Drop for Buffer<T>
     {
         unsafe
         {
-            if *self.count > 0
-            {
-                if self.locked_here { *self.lock = false; }
-                *self.count -= 1;
-                return
-            }
+            // Elements of a new Buffer of Buffers are zeroed, and own nothing until they are assigned.
+            if self.count.is_null() { return }
+            if self.locked_here { *self.lock = false; }
+            // Only the last Buffer frees the shared data, after every other Buffer released it.
+            if (*self.count).fetch_sub(1, Ordering::Release) != 1 { return }
+            std::sync::atomic::fence(Ordering::Acquire);
+            if !self.element.is_null() && std::mem::needs_drop::<T>() { std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(self.element, *self.len)); }
             let array_layout = std::alloc::Layout::array::<T>(*self.len).unwrap();
-            std::alloc::dealloc(self.element as *mut u8, array_layout);
+            if !self.element.is_null() && array_layout.size() > 0 { std::alloc::dealloc(self.element as *mut u8, array_layout); }
             std::alloc::dealloc(self.len as *mut u8, std::alloc::Layout::new::<usize>());
             std::alloc::dealloc(self.lock as *mut u8, std::alloc::Layout::new::<bool>());
-            std::alloc::dealloc(self.count as *mut u8, std::alloc::Layout::new::<usize>());
+            drop(Box::from_raw(self.count));
         }
     }
 }
 
 ///The buffer that pushes the whole buffer when index meets the size of the buffer. Generic T must be either f32 or f64.
+///
+///Data is ordered by time : index 0 is the oldest sample and index len - 1 is the newest sample once the buffer is full.
+///Until then, pushed data fills from index 0 upward. Setting index to len primes the buffer as full, so the next push drops
+///the sample at index 0 and stores the new sample at len - 1.
+///
+///Storage holds twice the length so the window slides forward on push instead of shifting, and is moved back to the front
+///only once every len pushes. Pushing is therefore O(1) amortised while the window stays contiguous for Deref.
 #[derive(Clone)]
 pub struct PushBuffer<T>
 {
     buffer : * mut T,
+    start : usize,
     index : usize,
     len : usize
 }
@@ -217,58 +326,87 @@ impl<T : Copy> PushBuffer<T>
 
     pub fn new(len : usize) -> Result<Self, LayoutError>
     {
-        let layout = Layout::array::<T>(len)?;
-        unsafe { Ok(PushBuffer { buffer : alloc_zeroed(layout) as * mut T , index : 0, len : len }) }
+        let layout = Layout::array::<T>(len * 2)?;
+        unsafe { Ok(PushBuffer { buffer : alloc_zeroed(layout) as * mut T, start : 0, index : 0, len }) }
     }
-    ///New PushBuffer from raw pointer.
-
-    pub fn from_raw(ptr : * mut T, len : usize) -> Self
+    ///New PushBuffer from raw pointer. The samples are copied to the front of new storage and the given memory is freed.
+    ///
+    ///# Safety
+    ///The PushBuffer takes ownership of the memory: `ptr` must point to `len` initialised elements allocated by the global allocator
+    ///with `Layout::array::<T>(len)`, and nothing else may use or free it afterwards.
+    pub unsafe fn from_raw(ptr : * mut T, len : usize) -> Result<Self, LayoutError>
     {
-        Self { buffer : ptr, index : 0, len }
+        let layout = Layout::array::<T>(len)?;
+        let pushbuffer = Self::new(len)?;
+        if layout.size() > 0
+        {
+            unsafe
+            {
+                std::ptr::copy_nonoverlapping(ptr, pushbuffer.buffer, len);
+                dealloc(ptr as * mut u8, layout);
+            }
+        }
+        Ok(pushbuffer)
     }
     ///Resizes the buffer.
 
     pub fn resize(&mut self, len : usize) -> Result<(), LayoutError>
     {
-        let dealloc_layout = std::alloc::Layout::array::<T>(self.len)?;
-        let alloc_layout = std::alloc::Layout::array::<T>(len)?;
+        let dealloc_layout = std::alloc::Layout::array::<T>(self.len * 2)?;
+        let alloc_layout = std::alloc::Layout::array::<T>(len * 2)?;
         unsafe
         {
             std::alloc::dealloc(self.buffer as * mut u8, dealloc_layout);
             self.buffer = std::alloc::alloc_zeroed(alloc_layout) as * mut T;
         }
+        self.len = len;
+        self.start = 0;
+        self.index = 0;
         Ok(())
     }
     ///Converts internal data chunk as silce
 
-    pub fn into_slice(&self) -> &[T] { unsafe { std::slice::from_raw_parts(self.buffer, self.len) } }
+    pub fn into_slice(&self) -> &[T] { unsafe { std::slice::from_raw_parts(self.buffer.add(self.start), self.len) } }
     ///Converts internal data chunk as mutable silce
 
-    pub fn into_slice_mut(&self) -> &mut[T] { unsafe{ std::slice::from_raw_parts_mut(self.buffer, self.len) } }
+    pub fn into_slice_mut(&self) -> &mut[T] { unsafe{ std::slice::from_raw_parts_mut(self.buffer.add(self.start), self.len) } }
     ///Pushes data to buffer.
 
     pub fn push(& mut self, value : T)
     {
-        if self.index <= self.len
+        if self.index < self.len
         {
-            unsafe { * self.buffer.offset(self.index as isize) = value; }
+            unsafe { * self.buffer.add(self.start + self.index) = value; }
             self.index += 1;
         }
-        else
+        else if self.len > 0
         {
-            unsafe
-                {
-                    (1..self.len).for_each(|x| * self.buffer.offset(x as isize - 1) = * self.buffer.offset(x as isize));
-                    * self.buffer.offset(self.index as isize) = value;
-                }
+            if self.start == self.len { self.compact(); }
+            unsafe { * self.buffer.add(self.start + self.len) = value; }
+            self.start += 1;
         }
     }
+    ///Returns the oldest sample, at index 0. None if nothing has been pushed since the index was 0.
+    pub fn oldest(&self) -> Option<T> { if self.len == 0 || self.index == 0 { None } else { Some(self[0]) } }
+    ///Returns the newest sample, at index - 1. None if nothing has been pushed since the index was 0.
+    pub fn newest(&self) -> Option<T> { if self.len == 0 || self.index == 0 { None } else { Some(self[self.index.min(self.len) - 1]) } }
     ///Get index.
     pub fn get_index(&self) -> usize { self.index }
     ///Set index.
-    pub fn set_index(&mut self, index : usize) { self.index = index; }
+    pub fn set_index(&mut self, index : usize)
+    {
+        self.compact();
+        self.index = index;
+    }
     ///Returns the length of the buffer.
     pub fn len(& self) -> usize { return self.len; }
+    #[inline]
+    fn compact(&mut self)
+    {
+        if self.start == 0 { return }
+        unsafe { std::ptr::copy(self.buffer.add(self.start), self.buffer, self.len); }
+        self.start = 0;
+    }
 }
 impl<T> std::ops::Index<usize> for PushBuffer<T>
 {
@@ -276,12 +414,12 @@ impl<T> std::ops::Index<usize> for PushBuffer<T>
 
     fn index(& self, index : usize) -> & Self::Output
     {
-        let real_index = if index > self.len
+        let real_index = if index >= self.len
         {
             eprintln!("Index out of range. Indexing to remain of given index divided by size of buffer");
             index % self.len
         } else { index };
-        let data = unsafe { self.buffer.offset(real_index as isize).as_ref() };
+        let data = unsafe { self.buffer.add(self.start + real_index).as_ref() };
         match data
         {
             None => { panic!("Access to invalid memory!"); }
@@ -293,12 +431,12 @@ impl<T> std::ops::IndexMut<usize> for PushBuffer<T>
 {
     fn index_mut(& mut self, index : usize) -> & mut Self::Output
     {
-        let real_index = if index > self.len
+        let real_index = if index >= self.len
         {
             eprintln!("Index out of range. Indexing to remain of given index divided by size of buffer");
             index % self.len
         } else { index };
-        let data = unsafe { self.buffer.offset(real_index as isize).as_mut() };
+        let data = unsafe { self.buffer.add(self.start + real_index).as_mut() };
         match data
         {
             None => { panic!("Access to invalid memory!"); }
@@ -319,10 +457,10 @@ impl<T> Drop for PushBuffer<T>
 {
     fn drop(&mut self)
     {
-        let layout = Layout::array::<T>(self.len as usize);
+        let layout = Layout::array::<T>(self.len * 2);
         match layout
         {
-            Ok(layout) => { unsafe { dealloc(self.buffer as * mut u8, layout); } }
+            Ok(layout) => { if layout.size() > 0 { unsafe { dealloc(self.buffer as * mut u8, layout); } } }
             Err(error) => { eprintln!("drop failed : {}", error); }
         }
     }
@@ -360,6 +498,7 @@ impl<T : Copy> CircularBuffer<T>
             std::alloc::dealloc(self.buffer as * mut u8, dealloc_layout);
             self.buffer = std::alloc::alloc_zeroed(alloc_layout) as * mut T;
         }
+        self.len = len;
         self.read = 0;
         self.write = 0;
 
@@ -373,22 +512,55 @@ impl<T : Copy> CircularBuffer<T>
     pub fn push(& mut self, value : T)
     {
         unsafe { * self.buffer.offset(self.write as isize) = value; }
-        if self.write < self.len() { self.write += 1; } else { self.write = 0; }
+        self.write = (self.write + 1) % self.len;
     }
     ///Reads next data of the buffer.
     pub fn next(& mut self) -> T
     {
         let value = unsafe { *self.buffer.offset(self.read as isize) };
-        if self.write < self.len() { self.read += 1; } else { self.read = 0; }
+        self.read = (self.read + 1) % self.len;
         value
     }
     ///Initializes write index.
     pub fn init_write(& mut self, index : usize) { self.write = index; }
     ///Initializes read index.
-    pub fn init_read(& mut self, index : usize) { self.write = index; }
+    pub fn init_read(& mut self, index : usize) { self.read = index; }
+    ///Returns the number of pushed data not yet read by next.
+    ///Equal read and write indices mean the buffer is empty, so one slot is kept free and at most len - 1 data can be waiting.
+    pub fn available(&self) -> usize { (self.write + self.len - self.read) % self.len.max(1) }
+    ///Returns the number of data that can be pushed before overwriting data not yet read, which is len - 1 - available.
+    pub fn free(&self) -> usize { self.len.saturating_sub(1) - self.available() }
+    ///Reads data pushed offset pushes ago, where 0 is the latest pushed data.
+    pub fn read_offset(&self, offset : usize) -> T { unsafe { *self.buffer.add((self.write + self.len - 1 - offset % self.len) % self.len) } }
     ///Returns the length of the buffer.
     pub fn len(& self) -> usize { return self.len; }
 }
+impl<T : crate::dsp::Sample> CircularBuffer<T>
+{
+    ///Reads between pushed data with linear interpolation, where offset 0.0 is the latest pushed data.
+    pub fn read_interpolated(&self, offset : T) -> T
+    {
+        let offset = offset.to_f64().max(0.0);
+        let whole = offset as usize;
+        let fraction = T::from_f64(offset - whole as f64);
+        let (y0, y1) = (self.read_offset(whole), self.read_offset(whole + 1));
+        y0 + (y1 - y0) * fraction
+    }
+    ///Reads between pushed data with Catmull-Rom interpolation, where offset 0.0 is the latest pushed data.
+    ///Data newer than the latest pushed data is taken as the latest pushed data.
+    pub fn read_cubic(&self, offset : T) -> T
+    {
+        let offset = offset.to_f64().max(0.0);
+        let whole = offset as usize;
+        let fraction = T::from_f64(offset - whole as f64);
+        let (y0, y1, y2, y3) = (self.read_offset(whole.saturating_sub(1)), self.read_offset(whole), self.read_offset(whole + 1), self.read_offset(whole + 2));
+        let (half, two) = (T::from_f64(0.5), T::from_f64(2.0));
+        let c1 = half * (y2 - y0);
+        let c2 = y0 - T::from_f64(2.5) * y1 + two * y2 - half * y3;
+        let c3 = half * (y3 - y0) + T::from_f64(1.5) * (y1 - y2);
+        ((c3 * fraction + c2) * fraction + c1) * fraction + y1
+    }
+}
 impl<T: Copy> std::ops::Deref for CircularBuffer<T>
 {
     type Target = [T];
@@ -407,3 +579,333 @@ impl<T> Drop for CircularBuffer<T>
         }
     }
 }
+
+///Lock-free ring buffer for handing blocks from a single producer thread to a single consumer thread.
+///
+///Share it with `Arc` and push from the audio callback while another thread pops, to feed metering or recording without
+///blocking the callback as `Mutex` does. Pushing and popping never wait : a second producer or consumer calling at the
+///same time transfers nothing.
+pub struct SpscRing<T>
+{
+    buffer : Box<[std::cell::UnsafeCell<T>]>,
+    head : AtomicUsize,
+    tail : AtomicUsize,
+    pushing : AtomicBool,
+    popping : AtomicBool
+}
+impl<T : Copy + Default> SpscRing<T>
+{
+    ///New SpscRing holding up to len elements.
+    pub fn new(len : usize) -> Self
+    {
+        Self
+        {
+            buffer : (0..len).map(|_| std::cell::UnsafeCell::new(T::default())).collect(),
+            head : AtomicUsize::new(0),
+            tail : AtomicUsize::new(0),
+            pushing : AtomicBool::new(false),
+            popping : AtomicBool::new(false)
+        }
+    }
+    ///Pushes as many elements of data as there is room for, and returns how many were pushed.
+    pub fn try_push(&self, data : &[T]) -> usize
+    {
+        if self.pushing.swap(true, Ordering::Acquire) { return 0 }
+        let head = self.head.load(Ordering::Relaxed);
+        let count = data.len().min(self.len() - head.wrapping_sub(self.tail.load(Ordering::Acquire)));
+        for (index, value) in data[..count].iter().enumerate()
+        {
+            // The consumer does not read slots from head until head is released below.
+            unsafe { *self.buffer[head.wrapping_add(index) % self.len()].get() = *value; }
+        }
+        self.head.store(head.wrapping_add(count), Ordering::Release);
+        self.pushing.store(false, Ordering::Release);
+        count
+    }
+    ///Pops as many elements as are available into data, and returns how many were popped.
+    pub fn try_pop(&self, data : &mut [T]) -> usize
+    {
+        if self.popping.swap(true, Ordering::Acquire) { return 0 }
+        let tail = self.tail.load(Ordering::Relaxed);
+        let count = data.len().min(self.head.load(Ordering::Acquire).wrapping_sub(tail));
+        for (index, value) in data[..count].iter_mut().enumerate()
+        {
+            // The producer does not overwrite slots from tail until tail is released below.
+            *value = unsafe { *self.buffer[tail.wrapping_add(index) % self.len()].get() };
+        }
+        self.tail.store(tail.wrapping_add(count), Ordering::Release);
+        self.popping.store(false, Ordering::Release);
+        count
+    }
+    ///Returns the number of elements ready to pop.
+    pub fn available(&self) -> usize { self.head.load(Ordering::Acquire).wrapping_sub(self.tail.load(Ordering::Acquire)) }
+    ///Returns true if there is nothing to pop.
+    pub fn is_empty(&self) -> bool { self.available() == 0 }
+    ///Returns the capacity of the ring.
+    pub fn len(&self) -> usize { self.buffer.len() }
+}
+unsafe impl<T : Send> Send for SpscRing<T> {}
+unsafe impl<T : Send> Sync for SpscRing<T> {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn pushbuffer_orders_by_time_after_priming()
+    {
+        let mut buffer = PushBuffer::<f64>::new(3).unwrap();
+        buffer.set_index(buffer.len());
+        for value in 1..=5 { buffer.push(value as f64); }
+        assert_eq!(buffer.to_vec(), vec![3.0, 4.0, 5.0]);
+        assert_eq!((buffer.oldest(), buffer.newest()), (Some(3.0), Some(5.0)));
+    }
+
+    #[test]
+    fn buffer_from_raw_indexes_the_given_data()
+    {
+        // A boxed slice is allocated with exactly Layout::array::<f64>(len), as from_raw requires.
+        let data = Box::into_raw(vec![1.0, 2.0, 3.0, 4.0].into_boxed_slice()) as * mut f64;
+        let mut buffer = unsafe { Buffer::from_raw(data, 4) };
+        assert_eq!((buffer.len(), buffer[0], buffer[3]), (4, 1.0, 4.0));
+        buffer.lock();
+        buffer[1] = -2.0;
+        buffer.unlock();
+        assert_eq!(buffer.to_vec(), vec![1.0, -2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn pushbuffer_matches_shifting_over_several_wrap_arounds()
+    {
+        // The former implementation shifted the whole window on each push once full.
+        let len = 5;
+        let mut buffer = PushBuffer::<f64>::new(len).unwrap();
+        let mut shifting = vec![0.0; len];
+        let mut index = 0;
+        for value in 0..(len * 7 + 3)
+        {
+            let value = value as f64;
+            buffer.push(value);
+            if index < len
+            {
+                shifting[index] = value;
+                index += 1;
+            }
+            else
+            {
+                shifting.copy_within(1..len, 0);
+                shifting[len - 1] = value;
+            }
+            assert_eq!(buffer.to_vec(), shifting);
+            assert_eq!(buffer.newest(), Some(value));
+        }
+    }
+
+    #[test]
+    fn metering_over_known_signals()
+    {
+        let mut buffer = Buffer::<f64>::new(4800);
+        assert_eq!((buffer.peak(), buffer.rms(), buffer.find_peak_sample()), (0.0, 0.0, (0, 0.0)));
+        assert_eq!((buffer.peak_db(), buffer.rms_db()), (f64::NEG_INFINITY, f64::NEG_INFINITY));
+        assert_eq!((Buffer::<f64>::new(0).peak(), Buffer::<f64>::new(0).rms()), (0.0, 0.0));
+
+        buffer.lock();
+        for index in 0..buffer.len() { buffer[index] = -0.5; }
+        buffer.unlock();
+        assert_eq!((buffer.peak(), buffer.rms(), buffer.find_peak_sample()), (0.5, 0.5, (0, -0.5)));
+        assert!((buffer.peak_db() + 6.0206).abs() < 1e-4);
+
+        // 100 whole cycles of a full-scale sine, with the positive peak at index 12.
+        buffer.lock();
+        for index in 0..buffer.len() { buffer[index] = (2.0 * std::f64::consts::PI * index as f64 / 48.0).sin(); }
+        buffer.unlock();
+        assert!((buffer.rms() - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+        assert!((buffer.rms_db() + 3.0103).abs() < 1e-4);
+        assert_eq!(buffer.find_peak_sample().0, 12);
+        assert!((buffer.peak() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spscring_hands_a_sequence_between_threads_intact()
+    {
+        // Run under Miri with `cargo miri test spscring` to check for data races, with a shorter sequence to keep it quick.
+        let total = if cfg!(miri) { 300 } else { 100_000 };
+        let ring = std::sync::Arc::new(SpscRing::<u32>::new(16));
+        let producer =
+        {
+            let ring = ring.clone();
+            std::thread::spawn(move ||
+            {
+                let mut next = 0;
+                while next < total
+                {
+                    // Blocks of 1 to 7 elements, often more than there is room for.
+                    let block : Vec<u32> = (next..total.min(next + 1 + next % 7)).collect();
+                    let pushed = ring.try_push(&block);
+                    next += pushed as u32;
+                    if pushed == 0 { std::thread::yield_now(); }
+                }
+            })
+        };
+        let mut received = Vec::with_capacity(total as usize);
+        let mut block = [0u32; 5];
+        while received.len() < total as usize
+        {
+            let popped = ring.try_pop(&mut block);
+            received.extend_from_slice(&block[..popped]);
+            if popped == 0 { std::thread::yield_now(); }
+        }
+        producer.join().unwrap();
+        assert!(received.iter().copied().eq(0..total));
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn ref_count_follows_live_buffers_in_nested_scopes()
+    {
+        let buffer = Buffer::<f64>::new(8);
+        assert_eq!(buffer.ref_count(), 1);
+        {
+            let first = buffer.clone();
+            assert_eq!((buffer.ref_count(), first.ref_count()), (2, 2));
+            {
+                let second = first.clone();
+                let third = second.clone();
+                assert_eq!((buffer.ref_count(), third.ref_count()), (4, 4));
+                drop(second);
+                assert_eq!(third.ref_count(), 3);
+            }
+            assert_eq!(first.ref_count(), 2);
+        }
+        assert_eq!(buffer.ref_count(), 1);
+        // Buffers from from_raw and Default count themselves alone.
+        assert_eq!((Buffer::<f64>::default().ref_count(), Buffer::<f64>::default().clone().ref_count()), (1, 2));
+        let data = Box::into_raw(vec![0.0; 4].into_boxed_slice()) as * mut f64;
+        let raw = unsafe { Buffer::from_raw(data, 4) };
+        let shared = raw.clone();
+        drop(raw);
+        assert_eq!(shared.ref_count(), 1);
+    }
+
+    #[test]
+    fn arithmetic_and_mixing_truncate_to_the_shorter_buffer()
+    {
+        let ramp = |len : usize, step : f64|
+        {
+            let mut buffer = Buffer::<f64>::new(len);
+            buffer.lock();
+            for index in 0..len { buffer[index] = index as f64 * step; }
+            buffer.unlock();
+            buffer
+        };
+        let mut sum = ramp(4, 1.0);
+        sum += &ramp(4, 10.0);
+        assert_eq!(sum.to_vec(), vec![0.0, 11.0, 22.0, 33.0]);
+        sum.mul_scalar(0.5);
+        assert_eq!(sum.to_vec(), vec![0.0, 5.5, 11.0, 16.5]);
+        sum.mix(&ramp(4, 1.0), -2.0);
+        assert_eq!(sum.to_vec(), vec![0.0, 3.5, 7.0, 10.5]);
+
+        // Longer sources stop at the end of the destination, and shorter ones leave the rest as is.
+        let mut short = ramp(2, 1.0);
+        short += &ramp(6, 1.0);
+        assert_eq!(short.to_vec(), vec![0.0, 2.0]);
+        let mut long = ramp(6, 1.0);
+        long.mix(&ramp(2, 1.0), 3.0);
+        long.copy_from(&Buffer::new(1));
+        assert_eq!(long.to_vec(), vec![0.0, 4.0, 2.0, 3.0, 4.0, 5.0]);
+        long.copy_from(&ramp(3, -1.0));
+        assert_eq!(long.to_vec(), vec![0.0, -1.0, -2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn circularbuffer_reads_between_pushed_data()
+    {
+        let mut buffer = CircularBuffer::<f64>::new(16).unwrap();
+        for value in 0..40 { buffer.push(value as f64); }
+        assert_eq!(buffer.read_offset(0), 39.0);
+        for offset in [0.0, 0.25, 2.5, 7.75, 13.0]
+        {
+            assert_eq!(buffer.read_interpolated(offset), 39.0 - offset);
+            // Cubic reads take data newer than the latest as the latest, so only follow the ramp from an offset of 1.0.
+            if offset >= 1.0 { assert!((buffer.read_cubic(offset) - (39.0 - offset)).abs() < 1e-12); }
+        }
+
+        // Sine of 32 samples per cycle, read 2.5 samples before the latest pushed data.
+        let phase = |index : f64| (std::f64::consts::TAU * index / 32.0).sin();
+        for index in 0..40 { buffer.push(phase(index as f64)); }
+        let expected = phase(39.0 - 2.5);
+        let (linear, cubic) = ((buffer.read_interpolated(2.5) - expected).abs(), (buffer.read_cubic(2.5) - expected).abs());
+        assert!(linear < 5e-3 && cubic < 1e-4, "{linear} {cubic}");
+    }
+
+    #[test]
+    fn true_peak_finds_overs_between_samples()
+    {
+        // Sine at a quarter of the sample rate, sampled 45 degrees off its peaks, so every sample sits 3dB below the peak of 1.2.
+        let mut buffer = Buffer::<f64>::new(4800);
+        buffer.lock();
+        for index in 0..buffer.len() { buffer[index] = 1.2 * (std::f64::consts::FRAC_PI_2 * index as f64 + std::f64::consts::FRAC_PI_4).sin(); }
+        buffer.unlock();
+        assert!(buffer.peak_db() < 0.0 && buffer.count_clipped_samples(1.0) == 0);
+        assert!(buffer.true_peak_db() > 0.0);
+        assert!((buffer.true_peak_db() - crate::dsp::ratio_to_db(1.2)).abs() < 0.1, "{}", buffer.true_peak_db());
+        // Peaks on the samples read the same either way.
+        buffer.lock();
+        for index in 0..buffer.len() { buffer[index] = 0.5 * (std::f64::consts::FRAC_PI_2 * index as f64).sin(); }
+        buffer.unlock();
+        assert!((buffer.true_peak() - buffer.peak()).abs() < 0.01);
+    }
+
+    #[test]
+    fn circularbuffer_wraps_at_the_requested_length()
+    {
+        let mut buffer = CircularBuffer::<f64>::new(100).unwrap();
+        for value in 0..100 { buffer.push(value as f64); }
+        assert_eq!(buffer.len(), 100);
+        assert_eq!(buffer.read_offset(99), 0.0);
+        let read : Vec<f64> = (0..100).map(|_| buffer.next()).collect();
+        assert_eq!(read, (0..100).map(|value| value as f64).collect::<Vec<f64>>());
+        // The 101st read starts over at the first pushed value, not at an unused slot past 100.
+        assert_eq!(buffer.next(), 0.0);
+    }
+
+
+    #[test]
+    fn clip_detection_counts_and_finds_injected_overs()
+    {
+        let mut buffer = Buffer::<f64>::new(100);
+        buffer.lock();
+        for index in 0..buffer.len() { buffer[index] = 0.9 * (index as f64 * 0.3).sin(); }
+        buffer.unlock();
+        assert_eq!(buffer.count_clipped_samples(1.0), 0);
+        assert_eq!(buffer.first_clip_position(1.0), None);
+
+        buffer.lock();
+        (buffer[17], buffer[42], buffer[80]) = (1.25, -1.5, 1.0);
+        buffer.unlock();
+        // Exactly 1.0 is not over the threshold.
+        assert_eq!(buffer.count_clipped_samples(1.0), 2);
+        assert_eq!(buffer.first_clip_position(1.0), Some(17));
+        assert_eq!(buffer.first_clip_position(1.3), Some(42));
+    }
+
+
+    #[test]
+    fn circularbuffer_available_counts_unread_data()
+    {
+        let mut buffer = CircularBuffer::<f64>::new(16).unwrap();
+        assert_eq!((buffer.available(), buffer.free()), (0, 15));
+        // Rounds of pushes and reads run the indices around the end of the buffer.
+        for (pushed, read) in [(10, 4), (9, 3), (3, 15), (15, 0), (0, 15)]
+        {
+            let before = buffer.available();
+            for value in 0..pushed { buffer.push(value as f64); }
+            for _ in 0..read { buffer.next(); }
+            assert_eq!(buffer.available(), before + pushed - read);
+            assert_eq!(buffer.free(), 15 - buffer.available());
+        }
+        assert_eq!(buffer.available(), 0);
+    }
+}
@@ -1,17 +1,100 @@
 use std::alloc::LayoutError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::VecDeque;
 use no_denormals::*;
 
 use crate::buffer::*;
+use crate::audiofile::AudioFile;
 
-/// Convert ratio to dB.
+/// Window functions and windowed sinc FIR design.
+pub mod window;
+
+/// 4x oversampling interpolation filter phases for true-peak detection, from ITU-R BS.1770.
+const TRUE_PEAK_FILTER : [[f64; 12]; 4] =
+[
+    [0.0017089843750, 0.0109863281250, -0.0196533203125, 0.0332031250000, -0.0594482421875, 0.1373291015625, 0.9721679687500, -0.1022949218750, 0.0476074218750, -0.0266113281250, 0.0148925781250, -0.0083007812500],
+    [-0.0291748046875, 0.0292968750000, -0.0517578125000, 0.0891113281250, -0.1665039062500, 0.4650878906250, 0.7797851562500, -0.2003173828125, 0.1015625000000, -0.0582275390625, 0.0330810546875, -0.0189208984375],
+    [-0.0189208984375, 0.0330810546875, -0.0582275390625, 0.1015625000000, -0.2003173828125, 0.7797851562500, 0.4650878906250, -0.1665039062500, 0.0891113281250, -0.0517578125000, 0.0292968750000, -0.0291748046875],
+    [-0.0083007812500, 0.0148925781250, -0.0266113281250, 0.0476074218750, -0.1022949218750, 0.9721679687500, 0.1373291015625, -0.0594482421875, 0.0332031250000, -0.0196533203125, 0.0109863281250, 0.0017089843750]
+];
+/// Delay in samples between the input of the true-peak detector and the peak it reports.
+const TRUE_PEAK_LATENCY : usize = 6;
+/// Length of the half-band filter of each 2x oversampling stage.
+const HALF_BAND_TAPS : usize = 63;
+
+/// Count of NaN and infinite samples replaced by processors.
+static BAD_SAMPLES : AtomicUsize = AtomicUsize::new(0);
+
+/// Floating point sample type of generic processors, implemented for f32 and f64.
+pub trait Sample : Copy + Default + Send + Sync + PartialOrd + std::fmt::Debug
+    + std::ops::Add<Output = Self> + std::ops::Sub<Output = Self> + std::ops::Mul<Output = Self> + std::ops::Div<Output = Self> + std::ops::Neg<Output = Self>
+    + std::ops::AddAssign + std::ops::SubAssign + std::ops::MulAssign
+{
+    /// Convert from f64, rounding to the precision of the type.
+    fn from_f64(value : f64) -> Self;
+    /// Convert to f64.
+    fn to_f64(self) -> f64;
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn exp(self) -> Self;
+    fn log10(self) -> Self;
+    fn powf(self, exponent : Self) -> Self;
+    fn min(self, other : Self) -> Self;
+    fn max(self, other : Self) -> Self;
+    fn is_finite(self) -> bool;
+}
+impl Sample for f32
+{
+    #[inline] fn from_f64(value : f64) -> Self { value as f32 }
+    #[inline] fn to_f64(self) -> f64 { self as f64 }
+    #[inline] fn abs(self) -> Self { self.abs() }
+    #[inline] fn sqrt(self) -> Self { self.sqrt() }
+    #[inline] fn exp(self) -> Self { self.exp() }
+    #[inline] fn log10(self) -> Self { self.log10() }
+    #[inline] fn powf(self, exponent : Self) -> Self { self.powf(exponent) }
+    #[inline] fn min(self, other : Self) -> Self { self.min(other) }
+    #[inline] fn max(self, other : Self) -> Self { self.max(other) }
+    #[inline] fn is_finite(self) -> bool { self.is_finite() }
+}
+impl Sample for f64
+{
+    #[inline] fn from_f64(value : f64) -> Self { value }
+    #[inline] fn to_f64(self) -> f64 { self }
+    #[inline] fn abs(self) -> Self { self.abs() }
+    #[inline] fn sqrt(self) -> Self { self.sqrt() }
+    #[inline] fn exp(self) -> Self { self.exp() }
+    #[inline] fn log10(self) -> Self { self.log10() }
+    #[inline] fn powf(self, exponent : Self) -> Self { self.powf(exponent) }
+    #[inline] fn min(self, other : Self) -> Self { self.min(other) }
+    #[inline] fn max(self, other : Self) -> Self { self.max(other) }
+    #[inline] fn is_finite(self) -> bool { self.is_finite() }
+}
+
+/// Replace NaN and infinity with 0.0, counting the replacement. Stateful processors apply this to their input.
+#[inline]
+pub fn sanitize<T : Sample>(input : T) -> T
+{
+    if input.is_finite() { return input }
+    BAD_SAMPLES.fetch_add(1, Ordering::Relaxed);
+    T::from_f64(0.0)
+}
+
+/// Get count of NaN and infinite samples replaced since the start or the last reset.
+pub fn bad_samples() -> usize { BAD_SAMPLES.load(Ordering::Relaxed) }
+
+/// Reset count of NaN and infinite samples.
+pub fn reset_bad_samples() { BAD_SAMPLES.store(0, Ordering::Relaxed); }
+
+/// Convert amplitude ratio to dB as 20 * log10(ratio), the inverse of db_to_ratio.
 #[inline]
-pub fn ratio_to_db(ratio : f64) -> f64 { ratio.log10() / 20.0 }
+pub fn ratio_to_db<T : Sample>(ratio : T) -> T { T::from_f64(20.0) * ratio.log10() }
 
 /// Convert dB to ratio.
 #[inline]
-pub fn db_to_ratio(db : f64) -> f64 { 10.0f64.powf(db / 20.0) }
+pub fn db_to_ratio<T : Sample>(db : T) -> T { T::from_f64(10.0).powf(db / T::from_f64(20.0)) }
 
 ///Buffer and window for convolution. Buffer stores data for continuation. Generic T must be either f32 or f64.
+///Window is the impulse response in time order : window[0] is applied to the current input and window[k] to the input k samples ago.
 pub struct Convolution<T : Clone + Default + Send + Sync>
 {
     buffer : PushBuffer<T>,
@@ -19,32 +102,176 @@ pub struct Convolution<T : Clone + Default + Send + Sync>
 }
 impl<T : std::ops::Add<Output = T> + std::ops::Mul<Output = T> + Send + Sync + Copy + Default> Convolution<T>
 {
-    pub fn new(data : &mut [T]) -> Result<Self, LayoutError>
+    ///New convolution with the impulse response copied from data.
+    pub fn new(data : &[T]) -> Result<Self, LayoutError>
     {
-        let mut window = Self
-        {
-            buffer : PushBuffer::<T>::new(data.len())?,
-            window : Buffer::from_raw(data.as_mut_ptr(), data.len()),
-        };
-        window.buffer.set_index(window.buffer.len());
-        Ok(window)
+        let mut window = Buffer::new(data.len());
+        window.lock();
+        window.copy_from_slice(data);
+        window.unlock();
+        let mut convolution = Self { buffer : PushBuffer::<T>::new(data.len())?, window };
+        convolution.buffer.set_index(convolution.buffer.len());
+        Ok(convolution)
+    }
+    ///Clear the history of input, so the tail of the last signal does not bleed into the next. Call between discontinuous inputs.
+    pub fn reset(&mut self)
+    {
+        self.buffer.set_index(self.buffer.len());
+        self.buffer.into_slice_mut().fill(T::default());
     }
     ///Convolve input data into window, then returns into output.
     pub fn run(& mut self, input : &Buffer<T>, output : &mut Buffer<T>)
     {
-        let mut data = T::default();
+        let len = self.window.len();
         no_denormals(||
         {
             for index in 0..input.len()
             {
                 self.buffer.push(input[index]);
-                (0..self.window.len()).for_each(|index| data = data + self.buffer[index] * self.window[index]);
+                // PushBuffer keeps the newest sample at len - 1, so the window is read backward against it.
+                let mut data = T::default();
+                (0..len).for_each(|tap| data = data + self.buffer[len - 1 - tap] * self.window[tap]);
                 output[index] = data;
             }
         });
     }
 }
 
+impl Convolution<f64>
+{
+    ///New convolution with the impulse response from the channel of the audio file. Sample rate of the file must match the processing sample rate.
+    pub fn from_audio_file(audio : &AudioFile, channel : usize, sample_rate : f64) -> Result<Self, ConvolutionError>
+    {
+        if channel >= audio.num_channel() { return Err(ConvolutionError::Channel(channel)) }
+        if audio.sample_rate() as f64 != sample_rate { return Err(ConvolutionError::SampleRate(audio.sample_rate() as f64, sample_rate)) }
+        let data = &audio.audio_buffer[channel];
+        if data.is_empty() { return Err(ConvolutionError::Empty) }
+
+        let mut window = Buffer::new(data.len());
+        window.lock();
+        window.copy_from_slice(data);
+        window.unlock();
+        let mut convolution = Self { buffer : PushBuffer::new(data.len()).map_err(ConvolutionError::Layout)?, window };
+        convolution.buffer.set_index(convolution.buffer.len());
+        Ok(convolution)
+    }
+}
+
+///Error while loading impulse response for convolution.
+#[derive(Debug)]
+pub enum ConvolutionError
+{
+    /// Failed to allocate buffer.
+    Layout(LayoutError),
+    /// Channel does not exist in the file.
+    Channel(usize),
+    /// Sample rate of the file and the processing sample rate does not match.
+    SampleRate(f64, f64),
+    /// Impulse response has no sample.
+    Empty
+}
+impl std::fmt::Display for ConvolutionError
+{
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Layout(error) => write!(f, "{}", error),
+            Self::Channel(channel) => write!(f, "channel {} does not exist in the file", channel),
+            Self::SampleRate(file, processing) => write!(f, "sample rate of the file {} does not match the processing sample rate {}", file, processing),
+            Self::Empty => write!(f, "impulse response is empty")
+        }
+    }
+}
+impl std::error::Error for ConvolutionError {}
+
+///Stereo convolution with separate impulse response for each channel.
+pub struct StereoConvolution
+{
+    left : Convolution<f64>,
+    right : Convolution<f64>
+}
+impl StereoConvolution
+{
+    ///New stereo convolution from the audio file. Mono file is used for both channels.
+    pub fn from_audio_file(audio : &AudioFile, sample_rate : f64) -> Result<Self, ConvolutionError>
+    {
+        let right = if audio.num_channel() > 1 { 1 } else { 0 };
+        Ok(Self { left : Convolution::from_audio_file(audio, 0, sample_rate)?, right : Convolution::from_audio_file(audio, right, sample_rate)? })
+    }
+    ///Convolve each channel into window, then returns into output.
+    pub fn run(&mut self, input_left : &Buffer<f64>, input_right : &Buffer<f64>, output_left : &mut Buffer<f64>, output_right : &mut Buffer<f64>)
+    {
+        self.left.run(input_left, output_left);
+        self.right.run(input_right, output_right);
+    }
+}
+
+/// Stereo imaging helpers for pairs of buffers.
+pub struct Stereo;
+impl Stereo
+{
+    /// Encode left and right sample into mid and side.
+    #[inline]
+    pub fn encode_ms(left : f64, right : f64) -> (f64, f64) { ((left + right) * 0.5, (left - right) * 0.5) }
+    /// Decode mid and side sample into left and right.
+    #[inline]
+    pub fn decode_ms(mid : f64, side : f64) -> (f64, f64) { (mid + side, mid - side) }
+    /// Left and right gain of constant power pan law, -3dB at center. Pan ranges from -1 for left to 1 for right.
+    #[inline]
+    pub fn pan_gains(pan : f64) -> (f64, f64)
+    {
+        let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f64::consts::FRAC_PI_4;
+        (angle.cos(), angle.sin())
+    }
+    /// Scale side signal of the locked buffers in place. 0 collapses to mono, 1 is unchanged, and above 1 widens.
+    pub fn width(left : &mut Buffer<f64>, right : &mut Buffer<f64>, amount : f64)
+    {
+        no_denormals(|| for index in 0..left.len().min(right.len())
+        {
+            let (mid, side) = Self::encode_ms(sanitize(left[index]), sanitize(right[index]));
+            (left[index], right[index]) = Self::decode_ms(mid, side * amount);
+        });
+    }
+    /// Scale side signal of raw slices in place, without locking any Buffer. Processes up to the shorter length of left and right.
+    pub fn width_slice(left : &mut [f64], right : &mut [f64], amount : f64)
+    {
+        no_denormals(|| for (left, right) in left.iter_mut().zip(right.iter_mut())
+        {
+            let (mid, side) = Self::encode_ms(sanitize(*left), sanitize(*right));
+            (*left, *right) = Self::decode_ms(mid, side * amount);
+        });
+    }
+    /// Pan mono raw slice into left and right slices with constant power pan law, without locking or allocating. Processes up to the shortest length.
+    pub fn pan_slice(mono : &[f64], left : &mut [f64], right : &mut [f64], pan : f64)
+    {
+        let (gain_left, gain_right) = Self::pan_gains(pan);
+        for ((input, left), right) in mono.iter().zip(left.iter_mut()).zip(right.iter_mut())
+        {
+            let input = sanitize(*input);
+            *left = input * gain_left;
+            *right = input * gain_right;
+        }
+    }
+    /// Pan mono buffer into new left and right buffers with constant power pan law.
+    pub fn pan(mono : &Buffer<f64>, pan : f64) -> (Buffer<f64>, Buffer<f64>)
+    {
+        let (gain_left, gain_right) = Self::pan_gains(pan);
+        let (mut left, mut right) = (Buffer::new(mono.len()), Buffer::new(mono.len()));
+        left.lock();
+        right.lock();
+        for index in 0..mono.len()
+        {
+            let input = sanitize(mono[index]);
+            left[index] = input * gain_left;
+            right[index] = input * gain_right;
+        }
+        left.unlock();
+        right.unlock();
+        (left, right)
+    }
+}
+
 ///Set saturation character for one side. Generic T must be either f32 or f64.
 pub struct Saturation<T>
 {
@@ -68,21 +295,6 @@ impl Saturation<f32>
 
         Self { ths, lim, gap, rad_pow, org }
     }
-    ///Process each data for non-linear behavior.
-    pub fn run(input : & Buffer<f32>, output : & mut Buffer<f32>, upper : Self, lower : Self)
-    {
-        no_denormals(||
-        {
-            for index in 0..input.len()
-            {
-                if input[index] > upper.lim + upper.gap { output[index] = upper.lim; }
-                else if input[index] > upper.ths { output[index] =  upper.org + (upper.rad_pow - (upper.lim - input[index]).powi(2)).sqrt(); }
-                else if input[index] < lower.lim - lower.gap { output[index] = upper.lim; }
-                else if input[index] < lower.ths { output[index] = lower.org - (lower.rad_pow - (lower.lim - input[index]).powi(2)).sqrt(); }
-                else { output[index] = input[index]; }
-            }
-        });
-    }
 }
 impl Saturation<f64>
 {
@@ -98,114 +310,2842 @@ impl Saturation<f64>
 
         Self { ths, lim, gap, rad_pow, org }
     }
+}
+impl<T : Sample> Saturation<T>
+{
+    ///Process single data for non-linear behavior.
+    #[inline]
+    pub fn process(input : T, upper : &Self, lower : &Self) -> T
+    {
+        if input > upper.lim + upper.gap { upper.lim }
+        else if input > upper.ths { upper.org + (upper.rad_pow - (upper.lim - input) * (upper.lim - input)).sqrt() }
+        else if input < lower.lim - lower.gap { lower.lim }
+        else if input < lower.ths { lower.org - (lower.rad_pow - (lower.lim - input) * (lower.lim - input)).sqrt() }
+        else { input }
+    }
     ///Process each data for non-linear behavior.
-    pub fn run(input : & Buffer<f64>, output : & mut Buffer<f64>, upper : Self, lower : Self)
+    pub fn run(input : & Buffer<T>, output : & mut Buffer<T>, upper : Self, lower : Self)
     {
         no_denormals(||
         {
-            for index in 0..input.len()
-            {
-                if input[index] > upper.lim + upper.gap { output[index] = upper.lim; }
-                else if input[index] > upper.ths { output[index] =  upper.org + (upper.rad_pow - (upper.lim - input[index]).powi(2)).sqrt(); }
-                else if input[index] < lower.lim - lower.gap { output[index] = upper.lim; }
-                else if input[index] < lower.ths { output[index] = lower.org - (lower.rad_pow - (lower.lim - input[index]).powi(2)).sqrt(); }
-                else { output[index] = input[index]; }
-            }
+            for index in 0..input.len() { output[index] = Self::process(input[index], &upper, &lower); }
+        });
+    }
+    ///Process raw slices for non-linear behavior, without locking any Buffer. Processes up to the shorter length of input and output.
+    ///A Saturation shapes one side only, so this is the upper side and the lower side is passed as well.
+    pub fn process_slice(&self, input : &[T], output : &mut [T], lower : &Self)
+    {
+        no_denormals(||
+        {
+            for (input, output) in input.iter().zip(output.iter_mut()) { *output = Self::process(*input, self, lower); }
         });
     }
 }
 
-/// Basic compressor unit.
-#[derive(Default)]
-pub struct Compression
+/// Polyphase state of one 2x oversampling stage.
+#[derive(Clone)]
+struct HalfBandStage
 {
-    pub threshold : f64,// Threshold in dB.
-    pub ratio : f64,    // Ratio of the compression.
-    pub attack : f64,   // Attack in ms.
-    pub release : f64,  // Release in ms.
-    pub makeup : f64,   // Makeup Gain in dB.
-    buffer : f64
+    up : [f64; HALF_BAND_TAPS.div_ceil(2)],
+    up_index : usize,
+    down : [f64; HALF_BAND_TAPS],
+    down_index : usize
 }
-impl Compression
+impl Default for HalfBandStage
 {
-    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>, buffer_size : usize, sample_rate : f64)
+    fn default() -> Self { Self { up : [0.0; HALF_BAND_TAPS.div_ceil(2)], up_index : 0, down : [0.0; HALF_BAND_TAPS], down_index : 0 } }
+}
+impl HalfBandStage
+{
+    // Zero-stuff one sample and filter it, computing only the two polyphase branches.
+    #[inline]
+    fn upsample(&mut self, coefficients : &[f64; HALF_BAND_TAPS], input : f64) -> [f64; 2]
     {
-        if input.len() != buffer_size || output.len() != buffer_size { return }
-        let real_gain = db_to_ratio(self.makeup);
-        let real_threshold = db_to_ratio(self.threshold);
+        let len = self.up.len();
+        self.up[self.up_index] = input;
+        let mut output = [0.0; 2];
+        for tap in 0..len
+        {
+            let data = self.up[(self.up_index + len - tap) % len];
+            output[0] += coefficients[2 * tap] * data;
+            if 2 * tap + 1 < HALF_BAND_TAPS { output[1] += coefficients[2 * tap + 1] * data; }
+        }
+        self.up_index = (self.up_index + 1) % len;
+        [output[0] * 2.0, output[1] * 2.0]
+    }
+    // Filter two samples and keep the first, so each stage delays by exactly half the filter length.
+    #[inline]
+    fn downsample(&mut self, coefficients : &[f64; HALF_BAND_TAPS], input : [f64; 2]) -> f64
+    {
+        self.down[self.down_index] = input[0];
+        let newest = self.down_index + HALF_BAND_TAPS;
+        let output = (0..HALF_BAND_TAPS).map(|tap| coefficients[tap] * self.down[(newest - tap) % HALF_BAND_TAPS]).sum();
+        self.down[(self.down_index + 1) % HALF_BAND_TAPS] = input[1];
+        self.down_index = (self.down_index + 2) % HALF_BAND_TAPS;
+        output
+    }
+}
+
+/// Runs a nonlinearity at 2x, 4x or 8x the sample rate to keep its harmonics from folding back as aliasing.
+/// Each 2x stage uses one windowed-sinc half-band filter for both upsampling and downsampling.
+#[derive(Clone)]
+pub struct Oversampler
+{
+    factor : usize,                             // Oversampling factor.
+    sample_rate : f64,                          // Sample rate before oversampling.
+    coefficients : [f64; HALF_BAND_TAPS],
+    stages : Vec<HalfBandStage>,
+    scratch : [Vec<f64>; 2]
+}
+impl Oversampler
+{
+    /// New oversampler. Factor is rounded up to a power of two, at most 8. Factor 1 runs the function as is.
+    pub fn new(factor : usize, sample_rate : f64) -> Self
+    {
+        let factor = factor.next_power_of_two().min(8);
+        let center = (HALF_BAND_TAPS / 2) as f64;
+        let mut coefficients = [0.0; HALF_BAND_TAPS];
+        for (tap, coefficient) in coefficients.iter_mut().enumerate()
+        {
+            let x = tap as f64 - center;
+            let sinc = if x == 0.0 { 0.5 } else { (std::f64::consts::FRAC_PI_2 * x).sin() / (std::f64::consts::PI * x) };
+            let phase = 2.0 * std::f64::consts::PI * tap as f64 / (HALF_BAND_TAPS - 1) as f64;
+            *coefficient = sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos());
+        }
+        Self { factor, sample_rate, coefficients, stages : vec![HalfBandStage::default(); factor.trailing_zeros() as usize], scratch : [vec![0.0; factor], vec![0.0; factor]] }
+    }
+    /// Get oversampling factor.
+    pub fn get_factor(&self) -> usize { self.factor }
+    /// Get sample rate before oversampling.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate before oversampling.
+    pub fn set_sample_rate(&mut self, sample_rate : f64) { self.sample_rate = sample_rate; }
+    /// Delay added by the filters, in samples at the sample rate.
+    pub fn latency(&self) -> f64
+    {
+        (0..self.stages.len()).fold(0.0, |latency, stage| latency + (HALF_BAND_TAPS - 1) as f64 / (1 << (stage + 1)) as f64)
+    }
+    /// Clear the state of the filters.
+    pub fn reset(&mut self) { self.stages.iter_mut().for_each(|stage| *stage = HalfBandStage::default()); }
+    /// Process single sample through the function at the oversampled rate.
+    #[inline]
+    pub fn process(&mut self, input : f64, function : impl Fn(f64) -> f64) -> f64
+    {
+        let [current, next] = &mut self.scratch;
+        current[0] = input;
+        let mut len = 1;
+        for stage in self.stages.iter_mut()
+        {
+            for index in 0..len { next[2 * index..2 * index + 2].copy_from_slice(&stage.upsample(&self.coefficients, current[index])); }
+            len *= 2;
+            std::mem::swap(current, next);
+        }
+        current.iter_mut().for_each(|data| *data = function(*data));
+        for stage in self.stages.iter_mut().rev()
+        {
+            len /= 2;
+            for index in 0..len { next[index] = stage.downsample(&self.coefficients, [current[2 * index], current[2 * index + 1]]); }
+            std::mem::swap(current, next);
+        }
+        current[0]
+    }
+    /// Process each data of the buffer through the function at the oversampled rate.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>, function : impl Fn(f64) -> f64)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index]), &function); } });
+    }
+}
+
+/// Level detection of the compressor.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetectionMode
+{
+    /// Absolute value of each sample.
+    #[default]
+    Peak,
+    /// Root mean square averaged over the RMS window.
+    Rms
+}
+
+/// Shape of the knee of the compression curve.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum KneeShape
+{
+    /// No knee. Compression starts at the threshold.
+    #[default]
+    Hard,
+    /// Quadratic curve between the knee edges.
+    Quadratic,
+    /// Slope eased with a cubic curve between the knee edges, continuous up to curvature.
+    Cubic
+}
+
+/// Basic compressor unit. Generic T must be either f32 or f64.
+pub struct Compression<T : Sample = f64>
+{
+    pub threshold : T,              // Threshold in dB.
+    pub ratio : T,                  // Ratio of the compression.
+    pub attack : T,                 // Attack in ms.
+    pub release : T,                // Release in ms.
+    pub makeup : T,                 // Makeup Gain in dB.
+    pub knee : T,                   // Knee width in dB, centered on the threshold.
+    pub knee_shape : KneeShape,     // Shape of the knee.
+    pub detection : DetectionMode,  // Level detection of the input.
+    pub rms_window : T,             // Averaging time of RMS detection in ms.
+    sample_rate : T,
+    mean_square : T,
+    buffer : T
+}
+impl<T : Sample> Compression<T>
+{
+    /// New compressor for the sample rate.
+    pub fn new(sample_rate : T) -> Self
+    {
+        let zero = T::from_f64(0.0);
+        Self { threshold : zero, ratio : zero, attack : zero, release : zero, makeup : zero, knee : zero, knee_shape : KneeShape::Hard, detection : DetectionMode::Peak, rms_window : T::from_f64(10.0), sample_rate, mean_square : zero, buffer : zero }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> T { self.sample_rate }
+    /// Set sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : T) { self.sample_rate = sample_rate; }
+    /// Get the gain in dB applied to the input level in dB, before attack and release.
+    pub fn compute_gain(&self, input_db : T) -> T
+    {
+        let (zero, one, half) = (T::from_f64(0.0), T::from_f64(1.0), T::from_f64(0.5));
+        let slope = one / self.ratio.max(one) - one;
+        let lower = self.threshold - self.knee * half;
+        let upper = self.threshold + self.knee * half;
+
+        if self.knee_shape == KneeShape::Hard || self.knee <= zero || input_db >= upper { return slope * (input_db - self.threshold).max(zero) }
+        if input_db <= lower { return zero }
+        let x = (input_db - lower) / self.knee;
+        match self.knee_shape
+        {
+            KneeShape::Quadratic => slope * self.knee * x * x * half,
+            _ => slope * self.knee * (x * x * x - x * x * x * x * half)
+        }
+    }
+    /// Compress the input by its own level. Buffers must be the same size as the buffer size.
+    pub fn run(&mut self, input : &Buffer<T>, output : &mut Buffer<T>, buffer_size : usize) { self.run_sidechain(input, input, output, buffer_size); }
+    /// Compress the input by the level of the sidechain. Buffers must be the same size as the buffer size.
+    pub fn run_sidechain(&mut self, input : &Buffer<T>, sidechain : &Buffer<T>, output : &mut Buffer<T>, buffer_size : usize)
+    {
+        self.compress(input, sidechain, output, None, buffer_size);
+    }
+    /// Compress the input by its own level, writing the gain reduction of each sample in dB to the reduction buffer.
+    pub fn run_metered(&mut self, input : &Buffer<T>, output : &mut Buffer<T>, reduction : &mut Buffer<T>, buffer_size : usize)
+    {
+        self.compress(input, input, output, Some(reduction), buffer_size);
+    }
+    /// Gain reduction applied to the last sample in dB, zero or negative. Makeup gain is not included.
+    pub fn last_gain_reduction_db(&self) -> T { self.buffer }
+    fn compress(&mut self, input : &Buffer<T>, sidechain : &Buffer<T>, output : &mut Buffer<T>, mut reduction : Option<&mut Buffer<T>>, buffer_size : usize)
+    {
+        if input.len() != buffer_size || sidechain.len() != buffer_size || output.len() != buffer_size { return }
+        if reduction.as_ref().is_some_and(|reduction| reduction.len() != buffer_size) { return }
+        let (one, millisecond) = (T::from_f64(1.0), T::from_f64(-1000.0));
+        let attack = one - (millisecond / (self.attack * self.sample_rate)).exp();
+        let release = one - (millisecond / (self.release * self.sample_rate)).exp();
+        let average = one - (millisecond / (self.rms_window * self.sample_rate)).exp();
 
         no_denormals(||
         {
             for index in 0..buffer_size
             {
-                if input[index] > real_threshold { self.buffer -= ratio_to_db((input[index] - real_threshold) / (self.ratio * (sample_rate / (self.attack * 1000.0)))); }
-                output[index] = input[index] * real_gain * db_to_ratio(self.buffer);
-                if self.buffer < 0.0 { self.buffer += self.buffer * self.release * 1000.0 / sample_rate; }
+                let data = sanitize(input[index]);
+                let detected = sanitize(sidechain[index]);
+                let level = match self.detection
+                {
+                    DetectionMode::Peak => detected.abs(),
+                    DetectionMode::Rms =>
+                    {
+                        self.mean_square += (detected * detected - self.mean_square) * average;
+                        self.mean_square.sqrt()
+                    }
+                };
+                let gain = self.compute_gain(ratio_to_db(level));
+                self.buffer += (gain - self.buffer) * if gain < self.buffer { attack } else { release };
+                output[index] = data * db_to_ratio(self.buffer + self.makeup);
+                if let Some(reduction) = reduction.as_mut() { reduction[index] = self.buffer; }
             }
         });
     }
 }
+impl<T : Sample> Default for Compression<T> { fn default() -> Self { Self::new(T::from_f64(44100.0)) } }
+
+/// Noise gate and downward expander. Below the threshold, the signal is attenuated by the ratio down to the range once the hold time elapses.
+/// Generic T must be either f32 or f64.
+pub struct Gate<T : Sample = f64>
+{
+    pub threshold : T,  // Threshold in dB.
+    pub ratio : T,      // Expansion ratio below the threshold. Infinity gates fully down to the range.
+    pub attack : T,     // Attack time of opening in ms.
+    pub hold : T,       // Time staying open after the level falls below the threshold in ms.
+    pub release : T,    // Release time of closing in ms.
+    pub range : T,      // Maximum attenuation in dB, positive.
+    sample_rate : T,
+    hold_count : usize,
+    buffer : T
+}
+impl<T : Sample> Gate<T>
+{
+    /// New gate for the sample rate.
+    pub fn new(sample_rate : T) -> Self
+    {
+        let zero = T::from_f64(0.0);
+        Self
+        {
+            threshold : T::from_f64(-40.0), ratio : T::from_f64(f64::INFINITY), attack : T::from_f64(0.1), hold : T::from_f64(10.0), release : T::from_f64(100.0), range : T::from_f64(60.0),
+            sample_rate, hold_count : 0, buffer : zero
+        }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> T { self.sample_rate }
+    /// Set sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : T) { self.sample_rate = sample_rate; }
+    /// Gain reduction applied to the last sample in dB, zero or negative.
+    pub fn last_gain_reduction_db(&self) -> T { self.buffer }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : T) -> T
+    {
+        let (attack, release, hold) = self.coefficients();
+        self.step(input, attack, release, hold)
+    }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<T>, output : &mut Buffer<T>)
+    {
+        let (attack, release, hold) = self.coefficients();
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.step(sanitize(input[index]), attack, release, hold); } });
+    }
+    #[inline]
+    fn coefficients(&self) -> (T, T, usize)
+    {
+        let (one, millisecond) = (T::from_f64(1.0), T::from_f64(-1000.0));
+        let hold = (self.hold.to_f64() * self.sample_rate.to_f64() / 1000.0).round().max(0.0) as usize;
+        (one - (millisecond / (self.attack * self.sample_rate)).exp(), one - (millisecond / (self.release * self.sample_rate)).exp(), hold)
+    }
+    #[inline]
+    fn step(&mut self, input : T, attack : T, release : T, hold : usize) -> T
+    {
+        let zero = T::from_f64(0.0);
+        let level = ratio_to_db(input.abs());
+        let gain = if level >= self.threshold
+        {
+            self.hold_count = hold;
+            zero
+        }
+        else if self.hold_count > 0
+        {
+            self.hold_count -= 1;
+            zero
+        }
+        else
+        {
+            let slope = self.ratio.max(T::from_f64(1.0)) - T::from_f64(1.0);
+            if slope > zero { ((level - self.threshold) * slope).max(-self.range).min(zero) } else { zero }
+        };
+        self.buffer += (gain - self.buffer) * if gain > self.buffer { attack } else { release };
+        input * db_to_ratio(self.buffer)
+    }
+}
+impl<T : Sample> Default for Gate<T> { fn default() -> Self { Self::new(T::from_f64(44100.0)) } }
 
-/// Basic limiter unit.
+/// Inter-sample peak detector with 4x oversampling.
 #[derive(Default)]
-pub struct Limit
+pub(crate) struct TruePeakDetector
+{
+    history : [f64; 12],
+    index : usize
+}
+impl TruePeakDetector
+{
+    /// Push data and return the highest absolute value reconstructed around the sample TRUE_PEAK_LATENCY samples ago.
+    #[inline]
+    pub(crate) fn process(&mut self, input : f64) -> f64
+    {
+        self.index = (self.index + 1) % self.history.len();
+        self.history[self.index] = input;
+        let mut peak = 0.0f64;
+        for phase in &TRUE_PEAK_FILTER
+        {
+            let mut data = 0.0;
+            for (tap, coefficient) in phase.iter().enumerate() { data += coefficient * self.history[(self.index + self.history.len() - tap) % self.history.len()]; }
+            peak = peak.max(data.abs());
+        }
+        peak
+    }
+}
+
+/// Basic limiter unit. With lookahead, gain reduction ramps in over the lookahead time so it is in place when the peak arrives.
+/// Generic T must be either f32 or f64.
+pub struct Limit<T : Sample = f64>
 {
-    pub gain : f64,     // Gain in dB.
-    pub ceiling : f64,  // Ceiling in dB.
-    pub release : f64,  // Release time in ms.
-    buffer : f64
+    pub gain : T,           // Gain in dB.
+    pub ceiling : T,        // Ceiling in dB.
+    pub release : T,        // Release time in ms.
+    pub true_peak : bool,   // Limit inter-sample peaks detected with 4x oversampling. Delays output by 6 samples.
+    pub lookahead : T,      // Lookahead time in ms. Delays output by the lookahead.
+    sample_rate : T,
+    buffer : T,
+    reduction : T,
+    detector : TruePeakDetector,
+    delay : [T; TRUE_PEAK_LATENCY],
+    delay_index : usize,
+    lookahead_len : usize,
+    audio : CircularBuffer<T>,      // Audio delayed by the lookahead.
+    target : VecDeque<(usize, T)>,  // Gain needed within the lookahead with the count of its sample, rising from the front so the front is the lowest.
+    count : usize,                  // Samples processed, to expire targets older than the lookahead.
+    held : CircularBuffer<T>,       // Lowest gain needed within the lookahead, averaged into the gain ramp.
+    held_sum : T
 }
-impl Limit
+impl<T : Sample> Limit<T>
 {
-    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>, buffer_size : usize, sample_rate : f64)
+    /// New limiter for the sample rate.
+    pub fn new(sample_rate : T) -> Self
+    {
+        let zero = T::from_f64(0.0);
+        Self
+        {
+            gain : zero, ceiling : zero, release : zero, true_peak : false, lookahead : zero, sample_rate, buffer : zero, reduction : zero,
+            detector : TruePeakDetector::default(), delay : [zero; TRUE_PEAK_LATENCY], delay_index : 0, lookahead_len : 0,
+            audio : CircularBuffer::new(1).unwrap(), target : VecDeque::new(), count : 0, held : CircularBuffer::new(1).unwrap(), held_sum : zero
+        }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> T { self.sample_rate }
+    /// Set sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : T) { self.sample_rate = sample_rate; }
+    /// Limit the input. Buffers must be the same size as the buffer size.
+    pub fn run(&mut self, input : &Buffer<T>, output : &mut Buffer<T>, buffer_size : usize) { self.limit(input, output, None, buffer_size); }
+    /// Limit the input, writing the gain reduction of each sample in dB to the reduction buffer.
+    pub fn run_metered(&mut self, input : &Buffer<T>, output : &mut Buffer<T>, reduction : &mut Buffer<T>, buffer_size : usize)
+    {
+        self.limit(input, output, Some(reduction), buffer_size);
+    }
+    /// Gain reduction applied to the last sample in dB, zero or negative. Input gain is not included.
+    pub fn last_gain_reduction_db(&self) -> T { self.reduction }
+    /// Delay of the output in samples, from true peak detection and lookahead.
+    pub fn latency_samples(&self) -> usize
+    {
+        (if self.true_peak { TRUE_PEAK_LATENCY } else { 0 }) + Self::lookahead_len(self.lookahead, self.sample_rate)
+    }
+    fn limit(&mut self, input : &Buffer<T>, output : &mut Buffer<T>, mut reduction : Option<&mut Buffer<T>>, buffer_size : usize)
     {
         if input.len() != buffer_size || output.len() != buffer_size { return }
+        if reduction.as_ref().is_some_and(|reduction| reduction.len() != buffer_size) { return }
+        let zero = T::from_f64(0.0);
         let real_gain = db_to_ratio(self.gain);
-        let real_ceiling = db_to_ratio(self.ceiling);
+        let release = (T::from_f64(1000.0) / (self.release * self.sample_rate)).min(T::from_f64(1.0));
+        let lookahead_len = Self::lookahead_len(self.lookahead, self.sample_rate);
+        if lookahead_len != self.lookahead_len
+        {
+            let len = lookahead_len.max(1);
+            if self.audio.resize(len).is_err() || self.held.resize(len).is_err() { return }
+            self.target.clear();
+            self.target.reserve(len + 1);
+            self.lookahead_len = lookahead_len;
+            self.held_sum = zero;
+        }
 
         no_denormals(||
         {
             for index in 0..buffer_size
             {
-                if input[index] * real_gain > real_ceiling { self.buffer -= ratio_to_db(input[index] * real_gain - real_ceiling); }
-                output[index] = input[index] * real_gain * db_to_ratio(self.buffer);
-                if self.buffer < 0.0 { self.buffer += self.buffer * self.release * 1000.0 / sample_rate; }
+                let mut data = sanitize(input[index]) * real_gain;
+                let peak = if self.true_peak
+                {
+                    let peak = T::from_f64(self.detector.process(data.to_f64()));
+                    std::mem::swap(&mut data, &mut self.delay[self.delay_index]);
+                    self.delay_index = (self.delay_index + 1) % TRUE_PEAK_LATENCY;
+                    peak.max(data.abs())
+                } else { data.abs() };
+                if self.lookahead_len > 0
+                {
+                    // Hold the lowest gain needed within the lookahead, then average it over the lookahead so the ramp ends on the peak.
+                    // Targets no lower than the new one can never be the lowest again, so the front stays the lowest in O(1) amortised.
+                    let target = (self.ceiling - ratio_to_db(peak)).min(zero);
+                    while self.target.back().is_some_and(|&(_, back)| back >= target) { self.target.pop_back(); }
+                    self.target.push_back((self.count, target));
+                    while self.target.front().is_some_and(|&(count, _)| self.count.wrapping_sub(count) > self.lookahead_len) { self.target.pop_front(); }
+                    self.count = self.count.wrapping_add(1);
+                    let held = self.target.front().map_or(zero, |&(_, target)| target);
+                    self.held_sum += held - self.held.next();
+                    self.held.push(held);
+                    let ramp = self.held_sum / T::from_f64(self.lookahead_len as f64);
+                    if ramp < self.buffer { self.buffer = ramp; }
+                    let delayed = self.audio.next();
+                    self.audio.push(data);
+                    data = delayed;
+                }
+                else if ratio_to_db(peak) + self.buffer > self.ceiling { self.buffer = self.ceiling - ratio_to_db(peak); }
+                output[index] = data * db_to_ratio(self.buffer);
+                self.reduction = self.buffer;
+                if let Some(reduction) = reduction.as_mut() { reduction[index] = self.buffer; }
+                self.buffer -= self.buffer * release;
             }
         });
     }
+    #[inline]
+    fn lookahead_len(lookahead : T, sample_rate : T) -> usize { (lookahead.to_f64() * sample_rate.to_f64() / 1000.0).round().max(0.0) as usize }
 }
+impl<T : Sample> Default for Limit<T> { fn default() -> Self { Self::new(T::from_f64(44100.0)) } }
 
-/// Basic delay unit.
-pub struct Delay
+/// Loudness measured over a whole programme.
+#[derive(Clone, Copy, Debug)]
+pub struct LoudnessReport
 {
-    time : f64,                 // Delay time in ms.
-    pub feedback : f64,         // Feedback in percent.
-    pub mix : f64,              // Mix in percent.
-    buffer : CircularBuffer<f64>// Buffer for delay
+    pub integrated : f64,       // Gated integrated loudness in LUFS.
+    pub loudness_range : f64,   // Loudness range in LU.
+    pub max_momentary : f64,    // Highest momentary loudness in LUFS.
+    pub max_short_term : f64,   // Highest short-term loudness in LUFS.
+    pub true_peak : f64         // Highest true peak in dBTP.
 }
-impl Delay
+
+/// Loudness meter after ITU-R BS.1770 and EBU R128, with K-weighting, gating and loudness range.
+/// Momentary loudness spans 400ms and short-term 3s, both updated every 100ms.
+pub struct LoudnessMeter
 {
-    pub fn new(time : f64, sample_rate : f64) -> Self
+    pub weights : Vec<f64>,         // Weight of each channel, 1.0 by default. Set 1.41 for surround and 0.0 for LFE.
+    sample_rate : f64,
+    filters : Vec<[Biquad; 2]>,     // K-weighting of each channel.
+    detectors : Vec<TruePeakDetector>,
+    block_len : usize,              // Samples in a 100ms block.
+    block_count : usize,            // Samples summed into the current block.
+    block_sum : f64,                // Weighted mean square sum of the current block.
+    blocks : [f64; 30],             // Mean square of the last 3s of blocks.
+    blocks_done : usize,
+    momentary : Vec<f64>,           // Mean square of every momentary window, for gating.
+    short_term : Vec<f64>,          // Mean square of every short-term window, for loudness range.
+    peak : f64
+}
+impl LoudnessMeter
+{
+    /// New loudness meter with the number of channels for the sample rate.
+    pub fn new(channels : usize, sample_rate : f64) -> Self
     {
-        Self { time, feedback : 50.0, mix : 50.0, buffer : CircularBuffer::new((time / (1000.0 * sample_rate)) as usize).unwrap() }
+        Self
+        {
+            weights : vec![1.0; channels], sample_rate, filters : (0..channels).map(|_| Self::k_weighting(sample_rate)).collect(),
+            detectors : (0..channels).map(|_| TruePeakDetector::default()).collect(), block_len : ((sample_rate * 0.1).round() as usize).max(1),
+            block_count : 0, block_sum : 0.0, blocks : [0.0; 30], blocks_done : 0, momentary : vec![], short_term : vec![], peak : 0.0
+        }
     }
-    pub fn get_time(&self) -> f64 { self.time }
-    pub fn set_time(&mut self, time : f64, sample_rate : f64)
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Clear every measurement.
+    pub fn reset(&mut self) { *self = Self { weights : std::mem::take(&mut self.weights), ..Self::new(self.filters.len(), self.sample_rate) }; }
+    /// Measure single frame, a sample of each channel.
+    pub fn process(&mut self, frame : &[f64])
     {
-        self.time = time;
-        self.buffer.resize((time * 1000.0 / sample_rate) as usize).unwrap();
+        for (channel, &data) in frame.iter().enumerate().take(self.filters.len())
+        {
+            let data = sanitize(data);
+            self.peak = self.peak.max(self.detectors[channel].process(data)).max(data.abs());
+            let [shelf, highpass] = &mut self.filters[channel];
+            let weighted = highpass.process(shelf.process(data));
+            self.block_sum += self.weights.get(channel).copied().unwrap_or(1.0) * weighted * weighted;
+        }
+        self.block_count += 1;
+        if self.block_count < self.block_len { return }
+
+        self.blocks.rotate_left(1);
+        self.blocks[29] = self.block_sum / self.block_len as f64;
+        self.blocks_done += 1;
+        self.block_sum = 0.0;
+        self.block_count = 0;
+        if self.blocks_done >= 4 { self.momentary.push(self.blocks[26..].iter().sum::<f64>() / 4.0); }
+        if self.blocks_done >= 30 { self.short_term.push(self.blocks.iter().sum::<f64>() / 30.0); }
     }
-    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>, buffer_size : usize)
+    /// Measure the buffers, one for each channel.
+    pub fn run(&mut self, input : &Buffer<Buffer<f64>>)
     {
-        if input.len() != buffer_size || output.len() != buffer_size { return }
+        let mut frame = vec![0.0; input.len()];
+        let len = input.iter().map(|channel| channel.len()).min().unwrap_or(0);
         no_denormals(||
         {
-            for index in 0..buffer_size
+            for index in 0..len
             {
-                let data = self.buffer.next();
-                output[index] = input[index] + data * self.mix / 100.0;
-                self.buffer.push(data * self.feedback / 100.0 + input[index]);
+                for (channel, data) in frame.iter_mut().enumerate() { *data = input[channel][index]; }
+                self.process(&frame);
             }
         });
     }
-}
\ No newline at end of file
+    /// Momentary loudness of the last 400ms in LUFS.
+    pub fn momentary(&self) -> f64 { self.momentary.last().map_or(f64::NEG_INFINITY, |&power| Self::loudness(power)) }
+    /// Short-term loudness of the last 3s in LUFS.
+    pub fn short_term(&self) -> f64 { self.short_term.last().map_or(f64::NEG_INFINITY, |&power| Self::loudness(power)) }
+    /// Highest momentary loudness in LUFS.
+    pub fn max_momentary(&self) -> f64 { Self::loudness(self.momentary.iter().fold(0.0, |max, &power| power.max(max))) }
+    /// Highest short-term loudness in LUFS.
+    pub fn max_short_term(&self) -> f64 { Self::loudness(self.short_term.iter().fold(0.0, |max, &power| power.max(max))) }
+    /// Gated integrated loudness in LUFS, with absolute gate at -70 LUFS and relative gate 10 LU below.
+    /// Negative infinity if no momentary window passes the gates, as for silence or less than 400ms.
+    pub fn integrated(&self) -> f64
+    {
+        let gated = Self::gate(&self.momentary, 10.0);
+        if gated.is_empty() { return f64::NEG_INFINITY }
+        Self::loudness(gated.iter().sum::<f64>() / gated.len() as f64)
+    }
+    /// Loudness range in LU, between the 10th and 95th percentile of gated short-term loudness.
+    pub fn loudness_range(&self) -> f64
+    {
+        let mut gated : Vec<f64> = Self::gate(&self.short_term, 20.0).into_iter().map(Self::loudness).collect();
+        if gated.is_empty() { return 0.0 }
+        gated.sort_by(f64::total_cmp);
+        let percentile = |ratio : f64| gated[((gated.len() - 1) as f64 * ratio).round() as usize];
+        percentile(0.95) - percentile(0.1)
+    }
+    /// Highest true peak in dBTP, from 4x oversampling.
+    pub fn true_peak(&self) -> f64 { ratio_to_db(self.peak) }
+    /// Every measurement over the programme so far.
+    pub fn report(&self) -> LoudnessReport
+    {
+        LoudnessReport
+        {
+            integrated : self.integrated(), loudness_range : self.loudness_range(), max_momentary : self.max_momentary(),
+            max_short_term : self.max_short_term(), true_peak : self.true_peak()
+        }
+    }
+    #[inline]
+    fn loudness(power : f64) -> f64 { -0.691 + 10.0 * power.log10() }
+    // Windows above -70 LUFS and above the relative gate below their mean.
+    fn gate(powers : &[f64], relative : f64) -> Vec<f64>
+    {
+        let absolute : Vec<f64> = powers.iter().copied().filter(|&power| Self::loudness(power) > -70.0).collect();
+        let threshold = Self::loudness(absolute.iter().sum::<f64>() / absolute.len() as f64) - relative;
+        absolute.into_iter().filter(|&power| Self::loudness(power) > threshold).collect()
+    }
+    // High shelf for the head and highpass of the revised low-frequency B curve, redesigned for the sample rate.
+    fn k_weighting(sample_rate : f64) -> [Biquad; 2]
+    {
+        let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let high = 10.0f64.powf(3.999843853973347 / 20.0);
+        let band = high.powf(0.4996667741545416);
+        let shelf = Biquad::new(high + band * k / q + k * k, 2.0 * (k * k - high), high - band * k / q + k * k, 1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k);
+        let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        // Only the feedback of the highpass is normalized, keeping the numerator of 1, -2, 1 as in the BS.1770 reference.
+        let a0 = 1.0 + k / q + k * k;
+        let highpass = Biquad::new(a0, -2.0 * a0, a0, a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k);
+        [shelf, highpass]
+    }
+}
+
+/// Interpolation of the delay line between samples.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum DelayInterpolation
+{
+    /// Delay time truncated to whole samples.
+    #[default]
+    Integer,
+    /// Linear interpolation between adjacent samples.
+    Linear,
+    /// Cubic Hermite interpolation over four samples.
+    Cubic
+}
+
+/// Basic delay unit. Minimum delay is a sample. Generic T must be either f32 or f64.
+pub struct Delay<T : Sample = f64>
+{
+    time : T,                               // Delay time in ms.
+    pub feedback : T,                       // Feedback in percent.
+    pub mix : T,                            // Mix in percent.
+    pub interpolation : DelayInterpolation, // Interpolation of sub-sample delay time.
+    sample_rate : T,                        // Sample rate.
+    buffer : CircularBuffer<T>              // Buffer for delay
+}
+impl<T : Sample> Delay<T>
+{
+    pub fn new(time : T, sample_rate : T) -> Self
+    {
+        Self
+        {
+            time, feedback : T::from_f64(50.0), mix : T::from_f64(50.0), interpolation : DelayInterpolation::Integer, sample_rate,
+            buffer : CircularBuffer::new(Self::capacity(time, sample_rate)).unwrap()
+        }
+    }
+    pub fn get_time(&self) -> T { self.time }
+    /// Set delay time in ms. The buffer is only reallocated, and cleared, when the time exceeds its capacity.
+    pub fn set_time(&mut self, time : T)
+    {
+        self.time = time;
+        if Self::capacity(time, self.sample_rate) > self.buffer.len() { self.reallocate(time); }
+    }
+    /// Reserve the buffer for delay time up to the maximum in ms, so automating the time never reallocates.
+    pub fn set_max_time(&mut self, max_time : T)
+    {
+        if Self::capacity(max_time, self.sample_rate) > self.buffer.len() { self.reallocate(max_time); }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> T { self.sample_rate }
+    /// Set sample rate. Resizes the buffer for the delay time, which clears it.
+    pub fn set_sample_rate(&mut self, sample_rate : T)
+    {
+        self.sample_rate = sample_rate;
+        self.reallocate(self.time);
+    }
+    #[inline]
+    fn delay_len(time : T, sample_rate : T) -> usize { ((time.to_f64() * sample_rate.to_f64() / 1000.0) as usize).max(1) }
+    // Room for the interpolation taps around the delay.
+    #[inline]
+    fn capacity(time : T, sample_rate : T) -> usize { Self::delay_len(time, sample_rate) + 3 }
+    fn reallocate(&mut self, time : T)
+    {
+        self.buffer.resize(Self::capacity(time, self.sample_rate)).unwrap();
+    }
+    // Delayed sample, where a delay of one sample reads the latest written sample.
+    #[inline]
+    fn read(&self) -> T
+    {
+        let delay = (self.time * self.sample_rate / T::from_f64(1000.0)).max(T::from_f64(1.0)).min(T::from_f64((self.buffer.len() - 3) as f64));
+        match self.interpolation
+        {
+            DelayInterpolation::Integer => self.buffer.read_offset(Self::delay_len(self.time, self.sample_rate).clamp(1, self.buffer.len() - 3) - 1),
+            DelayInterpolation::Linear => self.buffer.read_interpolated(delay - T::from_f64(1.0)),
+            DelayInterpolation::Cubic => self.buffer.read_cubic(delay - T::from_f64(1.0))
+        }
+    }
+    // Read the delayed sample and write the input with feedback.
+    #[inline]
+    fn step(&mut self, input : T) -> T
+    {
+        let data = self.read();
+        self.buffer.push(data * self.feedback / T::from_f64(100.0) + input);
+        data
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : T) -> T { input + self.step(input) * self.mix / T::from_f64(100.0) }
+    pub fn run(&mut self, input : &Buffer<T>, output : &mut Buffer<T>, buffer_size : usize)
+    {
+        if input.len() != buffer_size || output.len() != buffer_size { return }
+        no_denormals(|| { for index in 0..buffer_size { output[index] = self.process(sanitize(input[index])); } });
+    }
+}
+/// Stereo delay whose repeats alternate between channels, starting on the channel opposite to the input.
+pub struct PingPongDelay
+{
+    pub feedback : f64,     // Feedback in percent.
+    pub mix : f64,          // Mix in percent.
+    left : Delay,
+    right : Delay
+}
+impl PingPongDelay
+{
+    /// New ping-pong delay with the time between repeats in ms.
+    pub fn new(time : f64, sample_rate : f64) -> Self
+    {
+        Self { feedback : 50.0, mix : 50.0, left : Delay::new(time, sample_rate), right : Delay::new(time, sample_rate) }
+    }
+    /// Get delay time in ms.
+    pub fn get_time(&self) -> f64 { self.left.get_time() }
+    /// Set delay time in ms.
+    pub fn set_time(&mut self, time : f64)
+    {
+        self.left.set_time(time);
+        self.right.set_time(time);
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.left.get_sample_rate() }
+    /// Set sample rate. Clears the delay lines.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.left.set_sample_rate(sample_rate);
+        self.right.set_sample_rate(sample_rate);
+    }
+    /// Process single stereo sample.
+    #[inline]
+    pub fn process(&mut self, input_left : f64, input_right : f64) -> (f64, f64)
+    {
+        let (wet_left, wet_right) = (self.left.read(), self.right.read());
+        // Each input enters the opposite line, and each line feeds the other, so repeats alternate.
+        self.right.buffer.push(input_left + wet_left * self.feedback / 100.0);
+        self.left.buffer.push(input_right + wet_right * self.feedback / 100.0);
+        (input_left + wet_left * self.mix / 100.0, input_right + wet_right * self.mix / 100.0)
+    }
+    /// Process each data of the stereo buffers.
+    pub fn run_stereo(&mut self, input_left : &Buffer<f64>, input_right : &Buffer<f64>, output_left : &mut Buffer<f64>, output_right : &mut Buffer<f64>)
+    {
+        let len = input_left.len().min(input_right.len()).min(output_left.len()).min(output_right.len());
+        no_denormals(|| for index in 0..len
+        {
+            (output_left[index], output_right[index]) = self.process(sanitize(input_left[index]), sanitize(input_right[index]));
+        });
+    }
+}
+
+/// Second order IIR filter section in Direct Form II transposed. State persists between calls.
+#[derive(Clone, Copy, Default)]
+pub struct Biquad
+{
+    pub b0 : f64,   // Feedforward coefficient of the current input.
+    pub b1 : f64,   // Feedforward coefficient of the input a sample ago.
+    pub b2 : f64,   // Feedforward coefficient of the input two samples ago.
+    pub a1 : f64,   // Feedback coefficient of the output a sample ago.
+    pub a2 : f64,   // Feedback coefficient of the output two samples ago.
+    z1 : f64,
+    z2 : f64
+}
+impl Biquad
+{
+    /// New biquad from coefficients. Every coefficient is normalized by a0.
+    pub fn new(b0 : f64, b1 : f64, b2 : f64, a0 : f64, a1 : f64, a2 : f64) -> Self
+    {
+        Self { b0 : b0 / a0, b1 : b1 / a0, b2 : b2 / a0, a1 : a1 / a0, a2 : a2 / a0, z1 : 0.0, z2 : 0.0 }
+    }
+    /// RBJ cookbook lowpass.
+    pub fn lowpass(cutoff : f64, q : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(cutoff, q, sample_rate);
+        Self::new((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+    /// RBJ cookbook highpass.
+    pub fn highpass(cutoff : f64, q : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(cutoff, q, sample_rate);
+        Self::new((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+    /// RBJ cookbook bandpass with 0dB gain at the center.
+    pub fn bandpass(center : f64, q : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(center, q, sample_rate);
+        Self::new(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+    /// RBJ cookbook notch.
+    pub fn notch(center : f64, q : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(center, q, sample_rate);
+        Self::new(1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+    /// RBJ cookbook allpass with unity gain and 180 degrees of phase shift at the center.
+    pub fn allpass(center : f64, q : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(center, q, sample_rate);
+        Self::new(1.0 - alpha, -2.0 * cos, 1.0 + alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+    /// RBJ cookbook peaking equalizer with gain in dB at the center.
+    pub fn peaking(center : f64, q : f64, gain : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(center, q, sample_rate);
+        let a = 10.0_f64.powf(gain / 40.0);
+        Self::new(1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+    }
+    /// RBJ cookbook low shelf with gain in dB below the cutoff.
+    pub fn lowshelf(cutoff : f64, q : f64, gain : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(cutoff, q, sample_rate);
+        let a = 10.0_f64.powf(gain / 40.0);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Self::new(a * ((a + 1.0) - (a - 1.0) * cos + beta), 2.0 * a * ((a - 1.0) - (a + 1.0) * cos), a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta, -2.0 * ((a - 1.0) + (a + 1.0) * cos), (a + 1.0) + (a - 1.0) * cos - beta)
+    }
+    /// RBJ cookbook high shelf with gain in dB above the cutoff.
+    pub fn highshelf(cutoff : f64, q : f64, gain : f64, sample_rate : f64) -> Self
+    {
+        let (cos, alpha) = rbj_prototype(cutoff, q, sample_rate);
+        let a = 10.0_f64.powf(gain / 40.0);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Self::new(a * ((a + 1.0) + (a - 1.0) * cos + beta), -2.0 * a * ((a - 1.0) + (a + 1.0) * cos), a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            (a + 1.0) - (a - 1.0) * cos + beta, 2.0 * ((a - 1.0) - (a + 1.0) * cos), (a + 1.0) - (a - 1.0) * cos - beta)
+    }
+    /// Clear the state of the filter.
+    pub fn reset(&mut self)
+    {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64
+    {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+    /// Process each data of the buffer. State carries over between calls.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+}
+
+// Cosine of the normalized frequency and the bandwidth term shared by the RBJ cookbook designs.
+#[inline]
+fn rbj_prototype(frequency : f64, q : f64, sample_rate : f64) -> (f64, f64)
+{
+    let omega = 2.0 * std::f64::consts::PI * frequency / sample_rate;
+    (omega.cos(), omega.sin() / (2.0 * q))
+}
+
+/// Biquads processed in series for higher order filters.
+#[derive(Clone, Default)]
+pub struct BiquadCascade
+{
+    pub stages : Vec<Biquad>    // Second order sections, processed from the first.
+}
+impl BiquadCascade
+{
+    /// New cascade from second order sections.
+    pub fn new(stages : Vec<Biquad>) -> Self { Self { stages } }
+    /// Butterworth lowpass of the order. Odd order adds a first order section.
+    pub fn butterworth_lowpass(order : usize, cutoff : f64, sample_rate : f64) -> Self { Self::design(order, cutoff, sample_rate, false, 1.0, 1.0) }
+    /// Butterworth highpass of the order. Odd order adds a first order section.
+    pub fn butterworth_highpass(order : usize, cutoff : f64, sample_rate : f64) -> Self { Self::design(order, cutoff, sample_rate, true, 1.0, 1.0) }
+    /// Chebyshev type I lowpass of the order with passband ripple in dB. Cutoff is the edge of the passband.
+    pub fn chebyshev_lowpass(order : usize, ripple : f64, cutoff : f64, sample_rate : f64) -> Self
+    {
+        let (sinh, cosh) = chebyshev_warp(order, ripple);
+        let mut cascade = Self::design(order, cutoff, sample_rate, false, sinh, cosh);
+        cascade.scale_even_order_ripple(order, ripple);
+        cascade
+    }
+    /// Chebyshev type I highpass of the order with passband ripple in dB. Cutoff is the edge of the passband.
+    pub fn chebyshev_highpass(order : usize, ripple : f64, cutoff : f64, sample_rate : f64) -> Self
+    {
+        let (sinh, cosh) = chebyshev_warp(order, ripple);
+        let mut cascade = Self::design(order, cutoff, sample_rate, true, sinh, cosh);
+        cascade.scale_even_order_ripple(order, ripple);
+        cascade
+    }
+    /// Linkwitz-Riley lowpass of the order, which must be even. -6dB at the cutoff.
+    pub fn linkwitz_riley_lowpass(order : usize, cutoff : f64, sample_rate : f64) -> Self
+    {
+        let mut cascade = Self::butterworth_lowpass(order / 2, cutoff, sample_rate);
+        cascade.stages.extend_from_within(..);
+        cascade
+    }
+    /// Linkwitz-Riley highpass of the order, which must be even. -6dB at the cutoff.
+    pub fn linkwitz_riley_highpass(order : usize, cutoff : f64, sample_rate : f64) -> Self
+    {
+        let mut cascade = Self::butterworth_highpass(order / 2, cutoff, sample_rate);
+        cascade.stages.extend_from_within(..);
+        cascade
+    }
+    /// Clear the state of every stage.
+    pub fn reset(&mut self) { self.stages.iter_mut().for_each(Biquad::reset); }
+    /// Process single sample through every stage.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64 { self.stages.iter_mut().fold(input, |data, stage| stage.process(data)) }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+    // Bilinear transform of the analog prototype, whose poles are spread on an ellipse of sinh and cosh.
+    fn design(order : usize, cutoff : f64, sample_rate : f64, highpass : bool, sinh : f64, cosh : f64) -> Self
+    {
+        let k = 1.0 / (std::f64::consts::PI * cutoff / sample_rate).tan();
+        let mut stages = Vec::with_capacity(order.div_ceil(2));
+        for pair in 0..order / 2
+        {
+            let angle = std::f64::consts::PI * (2 * pair + 1) as f64 / (2 * order) as f64;
+            let (real, imag) = (sinh * angle.sin(), cosh * angle.cos());
+            // Lowpass section w^2 / (s^2 + a s + w^2). Highpass replaces s with 1 / s.
+            let (mut a, mut w2) = (2.0 * real, real * real + imag * imag);
+            if highpass
+            {
+                a /= w2;
+                w2 = 1.0 / w2;
+            }
+            let (b0, b1, b2) = if highpass { (k * k, -2.0 * k * k, k * k) } else { (w2, 2.0 * w2, w2) };
+            stages.push(Biquad::new(b0, b1, b2, k * k + a * k + w2, 2.0 * (w2 - k * k), k * k - a * k + w2));
+        }
+        if order % 2 == 1
+        {
+            let mut pole = sinh;
+            if highpass { pole = 1.0 / pole; }
+            let (b0, b1) = if highpass { (k, -k) } else { (pole, pole) };
+            stages.push(Biquad::new(b0, b1, 0.0, k + pole, pole - k, 0.0));
+        }
+        Self { stages }
+    }
+    // Even order Chebyshev peaks above unity in the passband, so it is lowered to ripple between -ripple and 0dB.
+    fn scale_even_order_ripple(&mut self, order : usize, ripple : f64)
+    {
+        if order % 2 == 1 || self.stages.is_empty() { return }
+        let gain = db_to_ratio(-ripple);
+        let stage = &mut self.stages[0];
+        stage.b0 *= gain;
+        stage.b1 *= gain;
+        stage.b2 *= gain;
+    }
+}
+
+/// Hyperbolic sine and cosine that place the Chebyshev type I poles for the order and ripple in dB.
+#[inline]
+fn chebyshev_warp(order : usize, ripple : f64) -> (f64, f64)
+{
+    let epsilon = (10.0f64.powf(ripple / 10.0) - 1.0).sqrt();
+    let mu = (1.0 / epsilon).asinh() / order.max(1) as f64;
+    (mu.sinh(), mu.cosh())
+}
+
+/// Band splitter of 4th order Linkwitz-Riley filters, with a band below, between and above the crossover frequencies.
+/// Lower bands pass through the allpass of every higher crossover, so the bands sum to an allpass of flat magnitude.
+#[derive(Clone, Default)]
+pub struct Crossover
+{
+    frequencies : Vec<f64>,
+    lowpass : Vec<BiquadCascade>,
+    highpass : Vec<BiquadCascade>,
+    allpass : Vec<Vec<Biquad>>
+}
+impl Crossover
+{
+    /// New crossover with the crossover frequencies in Hz, which are sorted. One frequency splits 2 bands, and two split 3 bands.
+    pub fn new(frequencies : &[f64], sample_rate : f64) -> Self
+    {
+        let mut frequencies = frequencies.to_vec();
+        frequencies.sort_by(f64::total_cmp);
+        let lowpass = frequencies.iter().map(|&frequency| BiquadCascade::linkwitz_riley_lowpass(4, frequency, sample_rate)).collect();
+        let highpass = frequencies.iter().map(|&frequency| BiquadCascade::linkwitz_riley_highpass(4, frequency, sample_rate)).collect();
+        // Sum of the 4th order lowpass and highpass is the 2nd order allpass of Butterworth Q.
+        let allpass = (0..frequencies.len()).map(|band| frequencies[band + 1..].iter().map(|&frequency| Biquad::allpass(frequency, std::f64::consts::FRAC_1_SQRT_2, sample_rate)).collect()).collect();
+        Self { frequencies, lowpass, highpass, allpass }
+    }
+    /// Get crossover frequencies in Hz, from the lowest.
+    pub fn get_frequencies(&self) -> &[f64] { &self.frequencies }
+    /// Get number of bands.
+    pub fn get_bands(&self) -> usize { self.frequencies.len() + 1 }
+    /// Clear the state of every filter.
+    pub fn reset(&mut self)
+    {
+        self.lowpass.iter_mut().chain(self.highpass.iter_mut()).for_each(BiquadCascade::reset);
+        self.allpass.iter_mut().flatten().for_each(Biquad::reset);
+    }
+    /// Process single sample into one sample per band, from the lowest.
+    pub fn process(&mut self, input : f64) -> Vec<f64>
+    {
+        let mut bands = vec![0.0; self.get_bands()];
+        self.process_into(input, &mut bands);
+        bands
+    }
+    /// Process single sample into the bands, from the lowest, without allocating. Bands past the slice are dropped.
+    #[inline]
+    pub fn process_into(&mut self, input : f64, bands : &mut [f64])
+    {
+        let mut rest = input;
+        for band in 0..self.frequencies.len()
+        {
+            let low = self.allpass[band].iter_mut().fold(self.lowpass[band].process(rest), |data, stage| stage.process(data));
+            rest = self.highpass[band].process(rest);
+            if let Some(output) = bands.get_mut(band) { *output = low; }
+        }
+        if let Some(output) = bands.get_mut(self.frequencies.len()) { *output = rest; }
+    }
+    /// Process each data of the buffer into one buffer per band, from the lowest.
+    pub fn run(&mut self, input : &Buffer<f64>, outputs : &mut [Buffer<f64>])
+    {
+        let len = outputs.iter().fold(input.len(), |len, output| len.min(output.len()));
+        let mut bands = vec![0.0; self.get_bands()];
+        no_denormals(|| for index in 0..len
+        {
+            self.process_into(sanitize(input[index]), &mut bands);
+            for (output, band) in outputs.iter_mut().zip(&bands) { output[index] = *band; }
+        });
+    }
+}
+
+/// Compressor of each band of a Linkwitz-Riley crossover, summed back together.
+/// Bands left at the default settings pass unchanged, so the sum stays flat in magnitude.
+pub struct MultibandCompressor
+{
+    pub makeup : f64,   // Makeup gain of the sum in dB.
+    sample_rate : f64,
+    crossover : Crossover,
+    bands : Vec<Compression>,
+    split : Vec<Buffer<f64>>,
+    compressed : Vec<Buffer<f64>>
+}
+impl MultibandCompressor
+{
+    /// New multiband compressor with the crossover frequencies in Hz, and a compressor for each band from the lowest.
+    pub fn new(frequencies : &[f64], sample_rate : f64) -> Self
+    {
+        let crossover = Crossover::new(frequencies, sample_rate);
+        let bands = (0..crossover.get_bands()).map(|_| Compression::new(sample_rate)).collect();
+        Self { makeup : 0.0, sample_rate, crossover, bands, split : vec![], compressed : vec![] }
+    }
+    /// Get number of bands.
+    pub fn get_bands(&self) -> usize { self.bands.len() }
+    /// Compressor of the band, from the lowest. Panics if the band is out of range.
+    pub fn band(&mut self, index : usize) -> &mut Compression { &mut self.bands[index] }
+    /// Get crossover frequencies in Hz, from the lowest.
+    pub fn get_frequencies(&self) -> &[f64] { self.crossover.get_frequencies() }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate. Clears the crossover.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.sample_rate = sample_rate;
+        self.crossover = Crossover::new(self.crossover.get_frequencies(), sample_rate);
+        self.bands.iter_mut().for_each(|band| band.set_sample_rate(sample_rate));
+    }
+    /// Delay of the output in samples. Compression has no lookahead, so it is always 0.
+    pub fn latency(&self) -> usize { 0 }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        let len = input.len().min(output.len());
+        if self.split.first().is_none_or(|buffer| buffer.len() != len)
+        {
+            let new_buffers = || (0..self.bands.len()).map(|_| { let mut buffer = Buffer::new(len); buffer.lock(); buffer }).collect::<Vec<_>>();
+            self.split = new_buffers();
+            self.compressed = new_buffers();
+        }
+        self.crossover.run(input, &mut self.split);
+        for ((band, split), compressed) in self.bands.iter_mut().zip(&self.split).zip(&mut self.compressed) { band.run(split, compressed, len); }
+        let makeup = db_to_ratio(self.makeup);
+        for index in 0..len { output[index] = self.compressed.iter().map(|band| band[index]).sum::<f64>() * makeup; }
+    }
+}
+
+/// Simultaneous outputs of the state-variable filter.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SvfOutputs
+{
+    pub lowpass : f64,
+    pub bandpass : f64,
+    pub highpass : f64,
+    pub notch : f64
+}
+
+/// Zero-delay-feedback state-variable filter, after the trapezoidal integrator topology of Cytomic.
+/// Stable for every cutoff below Nyquist, and self-oscillates as resonance goes to infinity.
+#[derive(Clone)]
+pub struct Svf
+{
+    cutoff : f64,       // Cutoff frequency in Hz.
+    resonance : f64,    // Resonance as Q.
+    sample_rate : f64,  // Sample rate.
+    k : f64,
+    a1 : f64,
+    a2 : f64,
+    a3 : f64,
+    ic1eq : f64,
+    ic2eq : f64
+}
+impl Svf
+{
+    /// New state-variable filter at 1kHz cutoff with Butterworth resonance.
+    pub fn new(sample_rate : f64) -> Self
+    {
+        let mut svf = Self { cutoff : 1000.0, resonance : std::f64::consts::FRAC_1_SQRT_2, sample_rate, k : 0.0, a1 : 0.0, a2 : 0.0, a3 : 0.0, ic1eq : 0.0, ic2eq : 0.0 };
+        svf.update();
+        svf
+    }
+    /// Get cutoff frequency in Hz.
+    pub fn get_cutoff(&self) -> f64 { self.cutoff }
+    /// Set cutoff frequency in Hz. Kept just below Nyquist.
+    pub fn set_cutoff(&mut self, cutoff : f64)
+    {
+        self.cutoff = cutoff;
+        self.update();
+    }
+    /// Get resonance as Q.
+    pub fn get_resonance(&self) -> f64 { self.resonance }
+    /// Set resonance as Q. Infinite Q makes the filter self-oscillate.
+    pub fn set_resonance(&mut self, resonance : f64)
+    {
+        self.resonance = resonance;
+        self.update();
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.sample_rate = sample_rate;
+        self.update();
+    }
+    /// Clear the state of the filter.
+    pub fn reset(&mut self)
+    {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> SvfOutputs
+    {
+        let v3 = input - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+        let highpass = input - self.k * v1 - v2;
+        SvfOutputs { lowpass : v2, bandpass : v1, highpass, notch : v2 + highpass }
+    }
+    /// Lowpass each data of the buffer.
+    pub fn run_lowpass(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])).lowpass; } });
+    }
+    #[inline]
+    fn update(&mut self)
+    {
+        let cutoff = self.cutoff.clamp(0.0, self.sample_rate * 0.499);
+        let g = (std::f64::consts::PI * cutoff / self.sample_rate).tan();
+        self.k = if self.resonance > 0.0 { 1.0 / self.resonance } else { 2.0 };
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+}
+
+/// One-pole DC blocker, y[n] = x[n] - x[n-1] + R * y[n-1].
+/// Place it after Saturation with different upper and lower curves, whose asymmetry adds DC offset that builds up downstream.
+#[derive(Clone)]
+pub struct DcBlocker
+{
+    pub coefficient : f64,  // Pole radius R. Closer to 1 lowers the cutoff.
+    sample_rate : f64,      // Sample rate.
+    input : f64,
+    output : f64
+}
+impl DcBlocker
+{
+    /// New DC blocker with R of 0.995 at 44.1kHz, scaled to keep the same cutoff at the sample rate.
+    pub fn new(sample_rate : f64) -> Self { Self { coefficient : Self::default_coefficient(sample_rate), sample_rate, input : 0.0, output : 0.0 } }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate. Resets the coefficient to the default of the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.sample_rate = sample_rate;
+        self.coefficient = Self::default_coefficient(sample_rate);
+    }
+    /// Clear the state of the filter.
+    pub fn reset(&mut self)
+    {
+        self.input = 0.0;
+        self.output = 0.0;
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64
+    {
+        self.output = input - self.input + self.coefficient * self.output;
+        self.input = input;
+        self.output
+    }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+    #[inline]
+    fn default_coefficient(sample_rate : f64) -> f64 { 0.995f64.powf(44100.0 / sample_rate) }
+}
+impl Default for DcBlocker { fn default() -> Self { Self::new(44100.0) } }
+
+/// Ramp shape of the smoothed value.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmoothingMode
+{
+    /// Same step each sample, suited to gain as a ratio.
+    #[default]
+    Linear,
+    /// Same ratio each sample, suited to frequency. Ramps linearly when crossing or starting from zero.
+    Exponential
+}
+
+/// Value ramping to its target over the ramp time, one sample at a time, to change parameters without clicks.
+pub struct SmoothedValue
+{
+    pub mode : SmoothingMode,   // Ramp shape, applied from the next target.
+    pub ramp : f64,             // Ramp time in ms, applied from the next target.
+    sample_rate : f64,
+    current : f64,
+    target : f64,
+    step : f64,
+    multiply : bool,
+    remaining : usize
+}
+impl SmoothedValue
+{
+    /// New smoothed value starting at the initial value.
+    pub fn new(initial : f64, ramp : f64, sample_rate : f64) -> Self
+    {
+        Self { mode : SmoothingMode::Linear, ramp, sample_rate, current : initial, target : initial, step : 0.0, multiply : false, remaining : 0 }
+    }
+    /// Get the value to reach.
+    pub fn get_target(&self) -> f64 { self.target }
+    /// Set the value to reach at the end of the ramp time.
+    pub fn set_target(&mut self, target : f64)
+    {
+        self.target = target;
+        self.remaining = (self.ramp * self.sample_rate / 1000.0).round().max(0.0) as usize;
+        if self.remaining == 0 { return self.snap() }
+        self.multiply = self.mode == SmoothingMode::Exponential && self.current * target > 0.0;
+        self.step = if self.multiply { (target / self.current).powf(1.0 / self.remaining as f64) } else { (target - self.current) / self.remaining as f64 };
+    }
+    /// Jump to the target without ramping.
+    pub fn snap(&mut self)
+    {
+        self.current = self.target;
+        self.remaining = 0;
+    }
+    /// Get the current value.
+    pub fn get_current(&self) -> f64 { self.current }
+    /// Return true while ramping to the target.
+    pub fn is_smoothing(&self) -> bool { self.remaining > 0 }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate, applied from the next target.
+    pub fn set_sample_rate(&mut self, sample_rate : f64) { self.sample_rate = sample_rate; }
+    /// Advance a sample and return the value.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> f64
+    {
+        if self.remaining == 0 { return self.current }
+        self.remaining -= 1;
+        if self.remaining == 0 { self.current = self.target; }
+        else if self.multiply { self.current *= self.step; }
+        else { self.current += self.step; }
+        self.current
+    }
+    /// Fill the buffer with the value of each sample.
+    pub fn run(&mut self, output : &mut Buffer<f64>)
+    {
+        for index in 0..output.len() { output[index] = self.next(); }
+    }
+}
+
+/// Delay line with sub-sample delay time, interpolated with first-order all-pass for flat magnitude response.
+/// Delay time changes are smoothed to avoid clicks. Minimum delay is 0.5 samples.
+pub struct FractionalDelay
+{
+    pub smoothing : f64,    // Smoothing time of delay time changes in ms.
+    max_delay : f64,        // Maximum delay time in ms.
+    sample_rate : f64,      // Sample rate.
+    target : f64,           // Delay in samples to reach.
+    current : f64,          // Smoothed delay in samples.
+    last : f64,             // Last output of the all-pass.
+    write : usize,
+    buffer : Vec<f64>
+}
+impl FractionalDelay
+{
+    /// New fractional delay with maximum delay time in ms.
+    pub fn new(max_delay : f64, sample_rate : f64) -> Self
+    {
+        Self { smoothing : 10.0, max_delay, sample_rate, target : 0.5, current : 0.5, last : 0.0, write : 0, buffer : vec![0.0; Self::capacity(max_delay, sample_rate)] }
+    }
+    /// Get delay time in samples.
+    pub fn get_delay_samples(&self) -> f64 { self.target }
+    /// Set delay time in samples. Clamped between 0.5 samples and the maximum delay.
+    pub fn set_delay_samples(&mut self, delay : f64) { self.target = delay.clamp(0.5, (self.buffer.len() - 3) as f64); }
+    /// Get delay time in ms.
+    pub fn get_delay_time(&self) -> f64 { self.target * 1000.0 / self.sample_rate }
+    /// Set delay time in ms.
+    pub fn set_delay_time(&mut self, time : f64) { self.set_delay_samples(time * self.sample_rate / 1000.0); }
+    /// Jump to the delay time without smoothing.
+    pub fn snap(&mut self) { self.current = self.target; }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate. Resizes and clears the buffer, keeping delay time in ms.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        let time = self.get_delay_time();
+        self.sample_rate = sample_rate;
+        self.buffer = vec![0.0; Self::capacity(self.max_delay, sample_rate)];
+        self.write = 0;
+        self.last = 0.0;
+        self.set_delay_time(time);
+        self.snap();
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64
+    {
+        let len = self.buffer.len();
+        self.buffer[self.write] = input;
+        self.current += (self.target - self.current) * (1.0 - (-1000.0 / (self.smoothing * self.sample_rate)).exp());
+
+        let mut integer = self.current.floor() as usize;
+        let mut fraction = self.current - integer as f64;
+        // Keep the fraction between 0.5 and 1.5 so the all-pass pole stays away from the unit circle.
+        if fraction < 0.5 && integer > 0
+        {
+            integer -= 1;
+            fraction += 1.0;
+        }
+        let coefficient = (1.0 - fraction) / (1.0 + fraction);
+        let near = self.buffer[(self.write + len - integer) % len];
+        let far = self.buffer[(self.write + len - integer - 1) % len];
+        self.last = coefficient * near + far - coefficient * self.last;
+        self.write = (self.write + 1) % len;
+        self.last
+    }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+    #[inline]
+    fn capacity(max_delay : f64, sample_rate : f64) -> usize { (max_delay * sample_rate / 1000.0).ceil() as usize + 3 }
+}
+
+/// Waveform of the oscillator.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Waveform
+{
+    #[default]
+    Sine,
+    Saw,
+    Square,
+    Triangle
+}
+
+/// Basic oscillator with hard sync, linear FM and PM. Saw and square are band-limited with PolyBLEP, except at the reset of hard sync.
+pub struct Oscillator
+{
+    pub waveform : Waveform,    // Waveform of the oscillator.
+    pub frequency : f64,        // Frequency in Hz.
+    sample_rate : f64,          // Sample rate.
+    phase : f64,                // Phase in cycles, from 0 to 1.
+    increment : f64,            // Phase increment of the last sample in cycles.
+    output_phase : f64,         // Phase of the last output, including phase modulation.
+    wrapped : bool              // True if the phase wrapped on the last sample.
+}
+impl Oscillator
+{
+    /// New oscillator for the sample rate.
+    pub fn new(waveform : Waveform, frequency : f64, sample_rate : f64) -> Self
+    {
+        let increment = frequency / sample_rate;
+        Self { waveform, frequency, sample_rate, phase : 0.0, increment, output_phase : (-increment).rem_euclid(1.0), wrapped : false }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : f64) { self.sample_rate = sample_rate; }
+    /// Get phase in cycles.
+    pub fn get_phase(&self) -> f64 { self.phase }
+    /// Reset phase to 0.
+    pub fn reset(&mut self)
+    {
+        self.phase = 0.0;
+        self.output_phase = (-self.frequency / self.sample_rate).rem_euclid(1.0);
+    }
+    /// Return true if the phase wrapped on the last sample, which is when the oscillator syncs others.
+    pub fn wrapped(&self) -> bool { self.wrapped }
+    /// Hard sync to the master oscillator. Call after processing the master and before processing this oscillator.
+    /// The reset is naive: the jump of the waveform is not band-limited, so it aliases at high frequencies.
+    pub fn sync(&mut self, master : &Oscillator)
+    {
+        if !master.wrapped || master.increment == 0.0 { return }
+        // Restart from where the master wrapped within the sample.
+        let elapsed = if master.increment > 0.0 { master.phase } else { 1.0 - master.phase };
+        self.phase = (elapsed / master.increment.abs() * self.frequency / self.sample_rate).rem_euclid(1.0);
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self) -> f64 { self.process_fm(0.0) }
+    /// Process single sample with linear FM, where the modulator offsets the frequency in Hz. Negative frequency runs the phase backwards.
+    #[inline]
+    pub fn process_fm(&mut self, modulator : f64) -> f64
+    {
+        let increment = (self.frequency + modulator) / self.sample_rate;
+        let output = self.render(self.phase, increment);
+        self.advance(increment);
+        output
+    }
+    /// Process single sample with PM, where the modulator offsets the phase in cycles.
+    #[inline]
+    pub fn process_pm(&mut self, modulator : f64) -> f64
+    {
+        let increment = self.frequency / self.sample_rate;
+        let phase = (self.phase + modulator).rem_euclid(1.0);
+        // Band-limit with the distance the modulated phase moved, wrapped to half a cycle.
+        let moved = (phase - self.output_phase + 0.5).rem_euclid(1.0) - 0.5;
+        let output = self.render(phase, moved);
+        self.advance(increment);
+        output
+    }
+    /// Fill the buffer with the oscillator output.
+    pub fn run(&mut self, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..output.len() { output[index] = self.process(); } });
+    }
+    /// Fill the buffer with the oscillator output, where each sample of the modulator offsets the frequency in Hz.
+    pub fn run_fm(&mut self, modulator : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..output.len().min(modulator.len()) { output[index] = self.process_fm(modulator[index]); } });
+    }
+    /// Fill the buffer with the oscillator output, where each sample of the modulator offsets the phase in cycles.
+    pub fn run_pm(&mut self, modulator : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..output.len().min(modulator.len()) { output[index] = self.process_pm(modulator[index]); } });
+    }
+    // Waveform at the phase, band-limited for the phase moving by the increment on this sample.
+    #[inline]
+    fn render(&mut self, phase : f64, increment : f64) -> f64
+    {
+        self.output_phase = phase;
+        let step = increment.abs().min(0.5);
+        match self.waveform
+        {
+            Waveform::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+            Waveform::Saw => 2.0 * phase - 1.0 - poly_blep(phase, step),
+            Waveform::Square =>
+            {
+                let square = if phase < 0.5 { 1.0 } else { -1.0 };
+                square + poly_blep(phase, step) - poly_blep((phase + 0.5) % 1.0, step)
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (((phase + 0.25) % 1.0) - 0.5).abs()
+        }
+    }
+    #[inline]
+    fn advance(&mut self, increment : f64)
+    {
+        let next = self.phase + increment;
+        self.increment = increment;
+        self.wrapped = !(0.0..1.0).contains(&next);
+        self.phase = next.rem_euclid(1.0);
+    }
+}
+
+/// Polynomial band-limited step residual for the phase and phase increment.
+#[inline]
+fn poly_blep(phase : f64, increment : f64) -> f64
+{
+    if increment <= 0.0 { return 0.0 }
+    if phase < increment
+    {
+        let x = phase / increment;
+        2.0 * x - x * x - 1.0
+    }
+    else if phase > 1.0 - increment
+    {
+        let x = (phase - 1.0) / increment;
+        x * x + 2.0 * x + 1.0
+    }
+    else { 0.0 }
+}
+
+/// Stage of the ADSR envelope.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum AdsrStage
+{
+    /// Silent, waiting for the gate.
+    #[default]
+    Idle,
+    /// Rising to 1.0.
+    Attack,
+    /// Falling to the sustain level.
+    Decay,
+    /// Holding the sustain level while gated.
+    Sustain,
+    /// Falling to 0.0 after the gate.
+    Release
+}
+
+/// Linear ADSR envelope from 0.0 to 1.0. Retriggering attacks from the current level, and release falls from wherever the gate ends.
+pub struct Adsr
+{
+    pub attack : f64,   // Attack time from 0.0 to 1.0 in ms.
+    pub decay : f64,    // Decay time from 1.0 to the sustain level in ms.
+    pub sustain : f64,  // Sustain level from 0.0 to 1.0.
+    pub release : f64,  // Release time from the level at the gate to 0.0 in ms.
+    sample_rate : f64,
+    stage : AdsrStage,
+    level : f64,
+    step : f64,
+    remaining : usize
+}
+impl Adsr
+{
+    /// New envelope for the sample rate.
+    pub fn new(sample_rate : f64) -> Self
+    {
+        Self { attack : 10.0, decay : 100.0, sustain : 0.7, release : 200.0, sample_rate, stage : AdsrStage::Idle, level : 0.0, step : 0.0, remaining : 0 }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate, applied from the next stage.
+    pub fn set_sample_rate(&mut self, sample_rate : f64) { self.sample_rate = sample_rate; }
+    /// Get current stage.
+    pub fn get_stage(&self) -> AdsrStage { self.stage }
+    /// Get current level.
+    pub fn get_level(&self) -> f64 { self.level }
+    /// Return true unless idle.
+    pub fn is_active(&self) -> bool { self.stage != AdsrStage::Idle }
+    /// Start the attack when on, or the release when off.
+    pub fn gate(&mut self, on : bool)
+    {
+        if on { self.enter(AdsrStage::Attack); }
+        else if self.stage != AdsrStage::Idle { self.enter(AdsrStage::Release); }
+    }
+    /// Return to idle at level 0.0 without release.
+    pub fn reset(&mut self)
+    {
+        self.stage = AdsrStage::Idle;
+        self.level = 0.0;
+    }
+    #[inline]
+    fn enter(&mut self, stage : AdsrStage)
+    {
+        let samples = |time : f64| (time * self.sample_rate / 1000.0).round().max(1.0);
+        let sustain = self.sustain.clamp(0.0, 1.0);
+        // Attack from a retriggered level keeps the full attack rate, so it takes the time of the rest of the way.
+        let (target, remaining) = match stage
+        {
+            AdsrStage::Attack => (1.0, ((1.0 - self.level) * samples(self.attack)).round().max(1.0)),
+            AdsrStage::Decay => (sustain, samples(self.decay)),
+            AdsrStage::Release => (0.0, samples(self.release)),
+            _ => (self.level, 0.0)
+        };
+        self.stage = stage;
+        self.remaining = remaining as usize;
+        self.step = if self.remaining > 0 { (target - self.level) / remaining } else { 0.0 };
+    }
+    /// Advance a sample and return the level.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> f64
+    {
+        match self.stage
+        {
+            AdsrStage::Idle => return self.level,
+            AdsrStage::Sustain =>
+            {
+                self.level = self.sustain.clamp(0.0, 1.0);
+                return self.level
+            }
+            _ => {}
+        }
+        self.remaining -= 1;
+        if self.remaining > 0
+        {
+            self.level += self.step;
+            return self.level
+        }
+        // Land exactly on the target of the stage, then move on.
+        match self.stage
+        {
+            AdsrStage::Attack =>
+            {
+                self.level = 1.0;
+                self.enter(AdsrStage::Decay);
+            }
+            AdsrStage::Decay =>
+            {
+                self.level = self.sustain.clamp(0.0, 1.0);
+                self.enter(AdsrStage::Sustain);
+            }
+            _ => self.reset()
+        }
+        self.level
+    }
+    /// Fill the control buffer with the level of each sample.
+    pub fn run(&mut self, output : &mut Buffer<f64>)
+    {
+        for index in 0..output.len() { output[index] = self.next(); }
+    }
+}
+
+/// Frequency of the MIDI note in Hz, where note 69 is A4 at 440Hz.
+#[inline]
+pub fn midi_note_to_hz(note : u8) -> f64 { 440.0 * 2.0f64.powf((note as f64 - 69.0) / 12.0) }
+
+// Voice of the polyphonic oscillator, releasing while no note is held.
+struct PolyVoice
+{
+    oscillator : Oscillator,
+    envelope : Adsr,
+    velocity : f64,
+    note : Option<u8>,
+    age : u64
+}
+
+/// Polyphonic oscillator with voice allocation and ADSR envelope for each voice.
+/// The oldest voice is stolen when every voice is busy.
+pub struct PolyOscillator
+{
+    pub waveform : Waveform,    // Waveform of every voice.
+    pub attack : f64,           // Attack time in ms.
+    pub decay : f64,            // Decay time in ms.
+    pub sustain : f64,          // Sustain level from 0.0 to 1.0.
+    pub release : f64,          // Release time in ms.
+    sample_rate : f64,
+    age : u64,
+    voices : Vec<PolyVoice>
+}
+impl PolyOscillator
+{
+    /// New polyphonic oscillator with the number of voices, at least one.
+    pub fn new(voices : usize, waveform : Waveform, sample_rate : f64) -> Self
+    {
+        let voices = (0..voices.max(1)).map(|_| PolyVoice { oscillator : Oscillator::new(waveform, 440.0, sample_rate), envelope : Adsr::new(sample_rate), velocity : 0.0, note : None, age : 0 }).collect();
+        Self { waveform, attack : 5.0, decay : 0.0, sustain : 1.0, release : 100.0, sample_rate, age : 0, voices }
+    }
+    /// Get number of voices.
+    pub fn get_voices(&self) -> usize { self.voices.len() }
+    /// Number of voices held or releasing.
+    pub fn active_voices(&self) -> usize { self.voices.iter().filter(|voice| !Self::is_free(voice)).count() }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.sample_rate = sample_rate;
+        for voice in self.voices.iter_mut()
+        {
+            voice.oscillator.set_sample_rate(sample_rate);
+            voice.envelope.set_sample_rate(sample_rate);
+        }
+    }
+    /// Start the note with MIDI velocity from 0 to 127. Velocity 0 releases the note.
+    pub fn note_on(&mut self, note : u8, velocity : u8)
+    {
+        if velocity == 0 { return self.note_off(note) }
+        self.age += 1;
+        let index = self.voices.iter().position(|voice| voice.note == Some(note))
+            .or_else(|| self.voices.iter().position(Self::is_free))
+            .unwrap_or_else(|| self.voices.iter().enumerate().min_by_key(|(_, voice)| voice.age).map_or(0, |(index, _)| index));
+        let voice = &mut self.voices[index];
+        if Self::is_free(voice) { voice.oscillator.reset(); }
+        voice.oscillator.frequency = midi_note_to_hz(note);
+        (voice.envelope.attack, voice.envelope.decay, voice.envelope.sustain, voice.envelope.release) = (self.attack, self.decay, self.sustain, self.release);
+        voice.envelope.gate(true);
+        voice.velocity = velocity.min(127) as f64 / 127.0;
+        voice.note = Some(note);
+        voice.age = self.age;
+    }
+    /// Release the note.
+    pub fn note_off(&mut self, note : u8)
+    {
+        for voice in self.voices.iter_mut().filter(|voice| voice.note == Some(note))
+        {
+            voice.envelope.release = self.release;
+            voice.envelope.gate(false);
+            voice.note = None;
+        }
+    }
+    /// Release every note.
+    pub fn all_notes_off(&mut self)
+    {
+        for note in self.voices.iter().filter_map(|voice| voice.note).collect::<Vec<_>>() { self.note_off(note); }
+    }
+    #[inline]
+    fn is_free(voice : &PolyVoice) -> bool { voice.note.is_none() && !voice.envelope.is_active() }
+    /// Process single sample of the sum of every voice.
+    #[inline]
+    pub fn process(&mut self) -> f64
+    {
+        let waveform = self.waveform;
+        self.voices.iter_mut().filter(|voice| !Self::is_free(voice)).map(|voice|
+        {
+            voice.oscillator.waveform = waveform;
+            voice.oscillator.process() * voice.envelope.next() * voice.velocity
+        }).sum()
+    }
+    /// Fill the buffer with the sum of every voice.
+    pub fn render(&mut self, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..output.len() { output[index] = self.process(); } });
+    }
+}
+
+/// Chorus of delay voices modulated by sine LFOs spread evenly in phase.
+pub struct Chorus
+{
+    pub rate : f64,         // LFO rate in Hz.
+    pub depth : f64,        // Modulation depth in ms, above and below the delay time.
+    pub delay : f64,        // Center delay time in ms.
+    pub feedback : f64,     // Feedback of each voice in percent.
+    pub mix : f64,          // Mix in percent.
+    voices : Vec<(Delay, Oscillator)>
+}
+impl Chorus
+{
+    /// New chorus with the number of voices, at least one.
+    pub fn new(voices : usize, sample_rate : f64) -> Self
+    {
+        let voices = (0..voices.max(1)).map(|_|
+        {
+            let mut delay = Delay::new(15.0, sample_rate);
+            delay.interpolation = DelayInterpolation::Linear;
+            delay.set_max_time(20.0);
+            (delay, Oscillator::new(Waveform::Sine, 0.8, sample_rate))
+        }).collect();
+        Self { rate : 0.8, depth : 3.0, delay : 15.0, feedback : 0.0, mix : 50.0, voices }
+    }
+    /// Get number of voices.
+    pub fn get_voices(&self) -> usize { self.voices.len() }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.voices[0].0.get_sample_rate() }
+    /// Set sample rate. Clears the delay lines.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        for (delay, lfo) in self.voices.iter_mut()
+        {
+            delay.set_sample_rate(sample_rate);
+            lfo.set_sample_rate(sample_rate);
+        }
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64
+    {
+        let count = self.voices.len() as f64;
+        let mut wet = 0.0;
+        for (voice, (delay, lfo)) in self.voices.iter_mut().enumerate()
+        {
+            lfo.frequency = self.rate;
+            delay.feedback = self.feedback;
+            delay.set_time(self.delay + self.depth * lfo.process_pm(voice as f64 / count));
+            wet += delay.step(input);
+        }
+        input + wet / count * self.mix / 100.0
+    }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+}
+
+/// Flanger of a short delay modulated by a sine LFO between zero and the depth.
+/// Through-zero mode delays the dry signal by half the depth, so the modulated delay passes in front of it.
+pub struct Flanger
+{
+    pub rate : f64,             // LFO rate in Hz.
+    pub depth : f64,            // Maximum delay time in ms.
+    pub feedback : f64,         // Feedback in percent, below 100.
+    pub mix : f64,              // Mix in percent.
+    pub through_zero : bool,    // Delay the dry signal by half the depth. Delays output by half the depth.
+    delay : Delay,
+    dry : Delay,
+    lfo : Oscillator
+}
+impl Flanger
+{
+    /// New flanger for the sample rate.
+    pub fn new(sample_rate : f64) -> Self
+    {
+        let mut delay = Delay::new(1.0, sample_rate);
+        delay.interpolation = DelayInterpolation::Linear;
+        delay.set_max_time(10.0);
+        let mut dry = Delay::new(1.0, sample_rate);
+        dry.interpolation = DelayInterpolation::Linear;
+        dry.feedback = 0.0;
+        dry.set_max_time(5.0);
+        Self { rate : 0.2, depth : 2.0, feedback : 50.0, mix : 50.0, through_zero : false, delay, dry, lfo : Oscillator::new(Waveform::Sine, 0.2, sample_rate) }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.lfo.get_sample_rate() }
+    /// Set sample rate. Clears the delay lines.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.delay.set_sample_rate(sample_rate);
+        self.dry.set_sample_rate(sample_rate);
+        self.lfo.set_sample_rate(sample_rate);
+    }
+    /// Process single sample.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64
+    {
+        self.lfo.frequency = self.rate;
+        self.delay.feedback = self.feedback.clamp(-99.0, 99.0);
+        self.delay.set_time(self.depth * (1.0 + self.lfo.process()) / 2.0);
+        let wet = self.delay.step(input);
+        let dry = if self.through_zero
+        {
+            self.dry.set_time(self.depth / 2.0);
+            self.dry.step(input)
+        } else { input };
+        dry + wet * self.mix / 100.0
+    }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+}
+
+// Tunings of Freeverb at 44.1kHz, in samples.
+const COMB_TUNING : [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING : [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD : usize = 23;
+
+// Lowpass feedback comb filter of the reverb.
+struct ReverbComb
+{
+    tuning : usize,
+    filter : f64,
+    buffer : CircularBuffer<f64>
+}
+impl ReverbComb
+{
+    fn new(tuning : usize, sample_rate : f64) -> Self
+    {
+        Self { tuning, filter : 0.0, buffer : CircularBuffer::new(reverb_len(tuning, sample_rate)).unwrap() }
+    }
+    fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.filter = 0.0;
+        self.buffer.resize(reverb_len(self.tuning, sample_rate)).unwrap();
+    }
+    #[inline]
+    fn process(&mut self, input : f64, feedback : f64, damping : f64) -> f64
+    {
+        let output = self.buffer.read_offset(self.buffer.len() - 1);
+        self.filter = output * (1.0 - damping) + self.filter * damping;
+        self.buffer.push(input + self.filter * feedback);
+        output
+    }
+}
+
+// Schroeder allpass filter of the reverb.
+struct ReverbAllpass
+{
+    tuning : usize,
+    buffer : CircularBuffer<f64>
+}
+impl ReverbAllpass
+{
+    fn new(tuning : usize, sample_rate : f64) -> Self
+    {
+        Self { tuning, buffer : CircularBuffer::new(reverb_len(tuning, sample_rate)).unwrap() }
+    }
+    fn set_sample_rate(&mut self, sample_rate : f64) { self.buffer.resize(reverb_len(self.tuning, sample_rate)).unwrap(); }
+    #[inline]
+    fn process(&mut self, input : f64) -> f64
+    {
+        let delayed = self.buffer.read_offset(self.buffer.len() - 1);
+        self.buffer.push(input + delayed * 0.5);
+        delayed - input
+    }
+}
+
+#[inline]
+fn reverb_len(tuning : usize, sample_rate : f64) -> usize { ((tuning as f64 * sample_rate / 44100.0).round() as usize).max(1) }
+
+/// Algorithmic reverb of parallel comb filters and series allpass filters, after Freeverb.
+pub struct Reverb
+{
+    pub room_size : f64,    // Room size in percent, which sets the decay time.
+    pub damping : f64,      // High frequency damping of the tail in percent.
+    pub width : f64,        // Stereo width of the tail in percent.
+    pub mix : f64,          // Mix in percent.
+    sample_rate : f64,
+    combs : [Vec<ReverbComb>; 2],
+    allpasses : [Vec<ReverbAllpass>; 2]
+}
+impl Reverb
+{
+    /// New reverb for the sample rate.
+    pub fn new(sample_rate : f64) -> Self
+    {
+        let combs = [0, STEREO_SPREAD].map(|spread| COMB_TUNING.iter().map(|tuning| ReverbComb::new(tuning + spread, sample_rate)).collect());
+        let allpasses = [0, STEREO_SPREAD].map(|spread| ALLPASS_TUNING.iter().map(|tuning| ReverbAllpass::new(tuning + spread, sample_rate)).collect());
+        Self { room_size : 50.0, damping : 50.0, width : 100.0, mix : 30.0, sample_rate, combs, allpasses }
+    }
+    /// Get sample rate.
+    pub fn get_sample_rate(&self) -> f64 { self.sample_rate }
+    /// Set sample rate. Rescales every delay line, which clears the tail.
+    pub fn set_sample_rate(&mut self, sample_rate : f64)
+    {
+        self.sample_rate = sample_rate;
+        for comb in self.combs.iter_mut().flatten() { comb.set_sample_rate(sample_rate); }
+        for allpass in self.allpasses.iter_mut().flatten() { allpass.set_sample_rate(sample_rate); }
+    }
+    // Feedback of the combs stays within 0.7 and 0.98 so the tail always decays.
+    #[inline]
+    fn coefficients(&self) -> (f64, f64) { (0.7 + 0.28 * self.room_size.clamp(0.0, 100.0) / 100.0, 0.4 * self.damping.clamp(0.0, 100.0) / 100.0) }
+    #[inline]
+    fn tail(&mut self, channel : usize, input : f64, feedback : f64, damping : f64) -> f64
+    {
+        let input = input * 0.015;
+        let mut output = self.combs[channel].iter_mut().map(|comb| comb.process(input, feedback, damping)).sum::<f64>();
+        for allpass in self.allpasses[channel].iter_mut() { output = allpass.process(output); }
+        output * 3.0
+    }
+    /// Process single sample with the left network.
+    #[inline]
+    pub fn process(&mut self, input : f64) -> f64
+    {
+        let (feedback, damping) = self.coefficients();
+        let mix = self.mix / 100.0;
+        input * (1.0 - mix) + self.tail(0, input, feedback, damping) * mix
+    }
+    /// Process each data of the buffer.
+    pub fn run(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>)
+    {
+        no_denormals(|| { for index in 0..input.len().min(output.len()) { output[index] = self.process(sanitize(input[index])); } });
+    }
+    /// Process each data of the stereo buffers, where both networks are fed with the sum of the inputs.
+    pub fn run_stereo(&mut self, input_left : &Buffer<f64>, input_right : &Buffer<f64>, output_left : &mut Buffer<f64>, output_right : &mut Buffer<f64>)
+    {
+        let (feedback, damping) = self.coefficients();
+        let mix = self.mix / 100.0;
+        let width = self.width.clamp(0.0, 100.0) / 100.0;
+        let (direct, cross) = (mix * (0.5 + width / 2.0), mix * (1.0 - width) / 2.0);
+        let len = input_left.len().min(input_right.len()).min(output_left.len()).min(output_right.len());
+        no_denormals(|| for index in 0..len
+        {
+            let (left, right) = (sanitize(input_left[index]), sanitize(input_right[index]));
+            let (tail_left, tail_right) = (self.tail(0, (left + right) * 0.5, feedback, damping), self.tail(1, (left + right) * 0.5, feedback, damping));
+            output_left[index] = left * (1.0 - mix) + tail_left * direct + tail_right * cross;
+            output_right[index] = right * (1.0 - mix) + tail_right * direct + tail_left * cross;
+        });
+    }
+}
+
+/// Quality of the resampler, trading filter length for steeper anti-aliasing.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ResamplerQuality
+{
+    /// 16 taps for each phase.
+    Low,
+    /// 32 taps for each phase.
+    #[default]
+    Medium,
+    /// 64 taps for each phase.
+    High
+}
+
+/// Polyphase windowed sinc sample rate converter of any rational ratio. Keeps filter state between calls for streaming.
+pub struct Resampler
+{
+    input_rate : usize,
+    output_rate : usize,
+    upsample : usize,
+    downsample : usize,
+    latency : usize,
+    phases : Vec<Vec<f64>>,
+    history : Vec<f64>,
+    write : usize,
+    phase : usize
+}
+impl Resampler
+{
+    /// New resampler from input sample rate to output sample rate in Hz.
+    pub fn new(input_rate : usize, output_rate : usize, quality : ResamplerQuality) -> Self
+    {
+        let (input_rate, output_rate) = (input_rate.max(1), output_rate.max(1));
+        let divisor = gcd(input_rate, output_rate);
+        let (upsample, downsample) = (output_rate / divisor, input_rate / divisor);
+        let (taps, rolloff, beta) = match quality
+        {
+            ResamplerQuality::Low => (16, 0.85, 6.0),
+            ResamplerQuality::Medium => (32, 0.9, 8.0),
+            ResamplerQuality::High => (64, 0.95, 10.0)
+        };
+        // Odd prototype keeps the group delay on the upsampled grid, so the latency is a whole number of output samples.
+        let cutoff = rolloff * input_rate.min(output_rate) as f64 / 2.0;
+        let mut prototype = window::design_lowpass_fir(cutoff, (input_rate * upsample) as f64, upsample * taps - 1, window::Window::Kaiser(beta));
+        prototype.push(0.0);
+        let phases = (0..upsample).map(|phase| (0..taps).map(|tap| prototype[phase + tap * upsample] * upsample as f64).collect()).collect();
+        let delay = (upsample * taps - 2) / 2;
+        let latency = delay / downsample;
+        Self { input_rate, output_rate, upsample, downsample, latency, phases, history : vec![0.0; taps * 2], write : 0, phase : delay - latency * downsample }
+    }
+    /// Get input sample rate.
+    pub fn get_input_rate(&self) -> usize { self.input_rate }
+    /// Get output sample rate.
+    pub fn get_output_rate(&self) -> usize { self.output_rate }
+    /// Delay of the filter in output samples.
+    pub fn latency(&self) -> usize { self.latency }
+    /// Clear the filter state.
+    pub fn reset(&mut self)
+    {
+        let delay = (self.history.len() / 2 * self.upsample - 2) / 2;
+        self.history.iter_mut().for_each(|sample| *sample = 0.0);
+        self.write = 0;
+        self.phase = delay - self.latency * self.downsample;
+    }
+    /// Push single input sample, appending output samples that become available.
+    pub fn push(&mut self, input : f64, output : &mut Vec<f64>)
+    {
+        let taps = self.history.len() / 2;
+        // History is mirrored, so the newest taps are always contiguous from the write index.
+        self.write = (self.write + taps - 1) % taps;
+        self.history[self.write] = input;
+        self.history[self.write + taps] = input;
+        let history = &self.history[self.write..self.write + taps];
+        while self.phase < self.upsample
+        {
+            output.push(self.phases[self.phase].iter().zip(history).map(|(coefficient, sample)| coefficient * sample).sum());
+            self.phase += self.downsample;
+        }
+        self.phase -= self.upsample;
+    }
+    /// Resample the buffer into new buffer, whose length depends on the filter state.
+    pub fn process(&mut self, input : &Buffer<f64>) -> Buffer<f64>
+    {
+        let mut samples = Vec::with_capacity(input.len() * self.upsample / self.downsample + 1);
+        no_denormals(|| for index in 0..input.len() { self.push(sanitize(input[index]), &mut samples); });
+        let mut output = Buffer::new(samples.len());
+        output.lock();
+        output.copy_from_slice(&samples);
+        output.unlock();
+        output
+    }
+}
+
+#[inline]
+fn gcd(a : usize, b : usize) -> usize { if b == 0 { a } else { gcd(b, a % b) } }
+
+/// Complex number of the spectrum.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct Complex
+{
+    pub re : f64,   // Real part.
+    pub im : f64    // Imaginary part.
+}
+impl Complex
+{
+    /// New complex number.
+    pub fn new(re : f64, im : f64) -> Self { Self { re, im } }
+    /// New complex number from magnitude and phase in radians.
+    pub fn from_polar(norm : f64, arg : f64) -> Self { Self { re : norm * arg.cos(), im : norm * arg.sin() } }
+    /// Magnitude.
+    pub fn norm(&self) -> f64 { self.re.hypot(self.im) }
+    /// Phase in radians.
+    pub fn arg(&self) -> f64 { self.im.atan2(self.re) }
+    /// Complex conjugate.
+    pub fn conj(&self) -> Self { Self { re : self.re, im : -self.im } }
+}
+impl std::ops::Add for Complex { type Output = Self; fn add(self, other : Self) -> Self { Self::new(self.re + other.re, self.im + other.im) } }
+impl std::ops::Sub for Complex { type Output = Self; fn sub(self, other : Self) -> Self { Self::new(self.re - other.re, self.im - other.im) } }
+impl std::ops::Mul for Complex { type Output = Self; fn mul(self, other : Self) -> Self { Self::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re) } }
+impl std::ops::Mul<f64> for Complex { type Output = Self; fn mul(self, gain : f64) -> Self { Self::new(self.re * gain, self.im * gain) } }
+
+/// In-place radix-2 FFT of power of two length. Inverse transform is scaled by 1 / length.
+pub fn fft(data : &mut [Complex], inverse : bool)
+{
+    let len = data.len();
+    if len < 2 || !len.is_power_of_two() { return }
+    let bits = len.trailing_zeros();
+    for index in 0..len
+    {
+        let reversed = index.reverse_bits() >> (usize::BITS - bits);
+        if index < reversed { data.swap(index, reversed); }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= len
+    {
+        let step = Complex::from_polar(1.0, sign * 2.0 * std::f64::consts::PI / size as f64);
+        for start in (0..len).step_by(size)
+        {
+            let mut twiddle = Complex::new(1.0, 0.0);
+            for index in start..start + size / 2
+            {
+                let odd = data[index + size / 2] * twiddle;
+                data[index + size / 2] = data[index] - odd;
+                data[index] = data[index] + odd;
+                twiddle = twiddle * step;
+            }
+        }
+        size *= 2;
+    }
+    if inverse { data.iter_mut().for_each(|bin| *bin = *bin * (1.0 / len as f64)); }
+}
+
+/// Short-time Fourier transform with periodic Hann windows and weighted overlap-add.
+/// Each frame is windowed on analysis and synthesis, and the output is divided by the overlapped window power, so an untouched spectrum reconstructs the input delayed by the FFT size.
+pub struct Stft
+{
+    size : usize,
+    hop : usize,
+    window : Vec<f64>,
+    input : Vec<f64>,
+    output : Vec<f64>,
+    weight : Vec<f64>,
+    ready : Vec<f64>,
+    spectrum : Vec<Complex>,
+    pending : usize
+}
+impl Stft
+{
+    /// New STFT with the FFT size, rounded up to a power of two, and the hop between frames in samples, up to half of the size.
+    pub fn new(size : usize, hop : usize) -> Self
+    {
+        let size = size.max(2).next_power_of_two();
+        let hop = hop.clamp(1, size / 2);
+        let mut window = window::hann(size + 1);
+        window.pop();
+        Self { size, hop, window, input : vec![0.0; size], output : vec![0.0; size], weight : vec![0.0; size], ready : vec![0.0; hop], spectrum : vec![Complex::default(); size], pending : 0 }
+    }
+    /// Get FFT size.
+    pub fn get_size(&self) -> usize { self.size }
+    /// Get hop between frames.
+    pub fn get_hop(&self) -> usize { self.hop }
+    /// Delay of the output in samples.
+    pub fn latency(&self) -> usize { self.size }
+    /// Clear every frame in progress.
+    pub fn reset(&mut self)
+    {
+        for buffer in [&mut self.input, &mut self.output, &mut self.weight, &mut self.ready] { buffer.iter_mut().for_each(|sample| *sample = 0.0); }
+        self.pending = 0;
+    }
+    /// Process each data of the buffer, calling the closure on the full spectrum of every frame.
+    pub fn process(&mut self, input : &Buffer<f64>, output : &mut Buffer<f64>, mut f : impl FnMut(&mut [Complex]))
+    {
+        no_denormals(|| for index in 0..input.len().min(output.len())
+        {
+            self.input[self.size - self.hop + self.pending] = sanitize(input[index]);
+            output[index] = self.ready[self.pending];
+            self.pending += 1;
+            if self.pending == self.hop
+            {
+                self.frame(&mut f);
+                self.pending = 0;
+            }
+        });
+    }
+    #[inline]
+    fn frame(&mut self, f : &mut impl FnMut(&mut [Complex]))
+    {
+        for (bin, (sample, gain)) in self.spectrum.iter_mut().zip(self.input.iter().zip(&self.window)) { *bin = Complex::new(sample * gain, 0.0); }
+        fft(&mut self.spectrum, false);
+        f(&mut self.spectrum);
+        fft(&mut self.spectrum, true);
+        for index in 0..self.size
+        {
+            self.output[index] += self.spectrum[index].re * self.window[index];
+            self.weight[index] += self.window[index] * self.window[index];
+        }
+        // The oldest hop is complete, as no later frame overlaps it.
+        for index in 0..self.hop { self.ready[index] = if self.weight[index] > 1e-9 { self.output[index] / self.weight[index] } else { 0.0 }; }
+        for buffer in [&mut self.input, &mut self.output, &mut self.weight]
+        {
+            buffer.rotate_left(self.hop);
+            let len = buffer.len();
+            buffer[len - self.hop..].iter_mut().for_each(|sample| *sample = 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn buffer(data : &[f64]) -> Buffer<f64>
+    {
+        let mut buffer = Buffer::new(data.len());
+        buffer.lock();
+        buffer.copy_from_slice(data);
+        buffer.unlock();
+        buffer
+    }
+
+    /// Gain of the process at the frequency in Hz, as the ratio of RMS of output to input once the process has settled.
+    fn magnitude(mut process : impl FnMut(f64) -> f64, frequency : f64, sample_rate : f64) -> f64
+    {
+        let (mut input, mut output) = (0.0, 0.0);
+        for index in 0..16384
+        {
+            let data = (std::f64::consts::TAU * frequency * index as f64 / sample_rate).sin();
+            let processed = process(data);
+            if index >= 8192
+            {
+                input += data * data;
+                output += processed * processed;
+            }
+        }
+        (output / input).sqrt()
+    }
+
+    /// Magnitude of each bin of the real data, up to Nyquist.
+    fn spectrum(data : &[f64]) -> Vec<f64>
+    {
+        let mut bins = data.iter().map(|&data| Complex::new(data, 0.0)).collect::<Vec<_>>();
+        fft(&mut bins, false);
+        bins[..data.len() / 2].iter().map(Complex::norm).collect()
+    }
+
+    #[test]
+    fn knee_is_continuous_at_both_edges()
+    {
+        for knee_shape in [KneeShape::Quadratic, KneeShape::Cubic]
+        {
+            let compression = Compression::<f64> { threshold : -20.0, ratio : 4.0, knee : 10.0, knee_shape, ..Compression::default() };
+            let slope = |input_db : f64| (compression.compute_gain(input_db + 1e-6) - compression.compute_gain(input_db - 1e-6)) / 2e-6;
+            for edge in [-25.0, -15.0]
+            {
+                assert!((compression.compute_gain(edge - 1e-9) - compression.compute_gain(edge + 1e-9)).abs() < 1e-6);
+                assert!((slope(edge - 1e-3) - slope(edge + 1e-3)).abs() < 1e-2);
+            }
+            assert_eq!(compression.compute_gain(-30.0), 0.0);
+            assert!((compression.compute_gain(-10.0) - -7.5).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn convolution_applies_the_first_tap_to_the_current_input()
+    {
+        let impulse = buffer(&[1.0, 0.0, 0.0, 0.0, 0.0]);
+        for (kernel, expected) in [([1.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0, 0.0]), ([0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.0, 0.0]), ([1.0, 0.5, 0.25], [1.0, 0.5, 0.25, 0.0, 0.0])]
+        {
+            let mut convolution = Convolution::new(&kernel).unwrap();
+            let mut output = Buffer::new(5);
+            output.lock();
+            convolution.run(&impulse, &mut output);
+            assert_eq!(output.to_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn convolution_matches_direct_form_across_blocks()
+    {
+        let kernel = [0.5, -0.25, 0.125, 1.0, -0.75, 0.3, 0.2];
+        let input = (0..200).map(|index| ((index * 37 % 23) as f64 - 11.0) / 11.0).collect::<Vec<_>>();
+        let mut convolution = Convolution::new(&kernel).unwrap();
+        let mut output = vec![];
+        for block in input.chunks(32)
+        {
+            let mut block_output = Buffer::new(block.len());
+            block_output.lock();
+            convolution.run(&buffer(block), &mut block_output);
+            output.extend(block_output.to_vec());
+        }
+        for (index, data) in output.iter().enumerate()
+        {
+            let expected = (0..kernel.len()).filter(|&tap| tap <= index).map(|tap| kernel[tap] * input[index - tap]).sum::<f64>();
+            assert!((data - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn biquad_designs_have_the_expected_response()
+    {
+        let mut lowpass = Biquad::lowpass(1000.0, std::f64::consts::FRAC_1_SQRT_2, 48000.0);
+        let mut highpass = Biquad::highpass(1000.0, std::f64::consts::FRAC_1_SQRT_2, 48000.0);
+        let (mut low, mut high) = (0.0, 0.0);
+        for _ in 0..10000 { (low, high) = (lowpass.process(1.0), highpass.process(1.0)); }
+        assert!((low - 1.0).abs() < 1e-9 && high.abs() < 1e-9);
+
+        let mut peaking = Biquad::peaking(1000.0, 1.0, 6.0, 48000.0);
+        let center = ratio_to_db(magnitude(|data| peaking.process(data), 1000.0, 48000.0));
+        assert!((center - 6.0).abs() < 0.05);
+        // Swept from 100Hz to 10kHz, the boost never exceeds the center and never cuts.
+        for frequency in (0..40).map(|step| 100.0 * 100.0_f64.powf(step as f64 / 39.0))
+        {
+            let mut peaking = Biquad::peaking(1000.0, 1.0, 6.0, 48000.0);
+            let gain = ratio_to_db(magnitude(|data| peaking.process(data), frequency, 48000.0));
+            assert!(gain > -0.01 && gain <= center + 0.01);
+        }
+    }
+
+    #[test]
+    fn svf_outputs_sum_consistently_and_track_cutoff()
+    {
+        let mut svf = Svf::new(48000.0);
+        svf.set_resonance(2.0);
+        for index in 0..1000
+        {
+            let input = ((index * 7919 % 101) as f64 - 50.0) / 50.0;
+            let outputs = svf.process(input);
+            assert!((outputs.notch - (outputs.lowpass + outputs.highpass)).abs() < 1e-12);
+            assert!((outputs.lowpass + 0.5 * outputs.bandpass + outputs.highpass - input).abs() < 1e-12);
+        }
+        // With Butterworth resonance, lowpass and highpass are 3dB down at the cutoff.
+        for cutoff in [100.0, 1000.0, 5000.0, 15000.0]
+        {
+            let mut svf = Svf::new(48000.0);
+            svf.set_cutoff(cutoff);
+            assert!((ratio_to_db(magnitude(|data| svf.process(data).lowpass, cutoff, 48000.0)) + 3.01).abs() < 0.05);
+            svf.reset();
+            assert!((ratio_to_db(magnitude(|data| svf.process(data).highpass, cutoff, 48000.0)) + 3.01).abs() < 0.05);
+        }
+        let input = buffer(&(0..64).map(|index| (index as f64 * 0.3).sin()).collect::<Vec<_>>());
+        let (mut svf, mut reference) = (Svf::new(48000.0), Svf::new(48000.0));
+        let mut output = Buffer::new(64);
+        output.lock();
+        svf.run_lowpass(&input, &mut output);
+        for index in 0..64 { assert_eq!(output[index], reference.process(input[index]).lowpass); }
+    }
+
+    #[test]
+    fn dc_blocker_removes_dc_and_passes_100hz()
+    {
+        let mut blocker = DcBlocker::new(48000.0);
+        let mut output = 1.0;
+        for _ in 0..48000 { output = blocker.process(0.5); }
+        assert!(output.abs() < 1e-6);
+        let mut blocker = DcBlocker::new(48000.0);
+        assert!(magnitude(|data| blocker.process(data), 100.0, 48000.0) > 0.9);
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing_of_hard_clipping()
+    {
+        // 15kHz falls on bin 1280 of 4096 at 48kHz, so its harmonics and their aliases fall on bins without leakage.
+        let aliasing = |factor : usize|
+        {
+            let mut oversampler = Oversampler::new(factor, 48000.0);
+            let output = (0..8192).map(|index| oversampler.process((std::f64::consts::TAU * 1280.0 * index as f64 / 4096.0).sin(), |data| data.clamp(-0.5, 0.5))).collect::<Vec<_>>();
+            let bins = spectrum(&output[4096..]);
+            bins[1..1200].iter().map(|bin| bin * bin).sum::<f64>() / (bins[1280] * bins[1280])
+        };
+        assert!(aliasing(4) < aliasing(1) * 0.01);
+    }
+
+    #[test]
+    fn rms_detection_reacts_less_to_bursts_and_sidechain_ducks()
+    {
+        // Twenty full scale samples in silence.
+        let burst = buffer(&(0..4800).map(|index| if (2400..2420).contains(&index) { 1.0 } else { 0.0 }).collect::<Vec<_>>());
+        let deepest = |detection : DetectionMode|
+        {
+            let mut compression = Compression::<f64> { threshold : -20.0, ratio : 4.0, attack : 0.1, release : 50.0, detection, ..Compression::new(48000.0) };
+            let (mut output, mut reduction) = (Buffer::new(4800), Buffer::new(4800));
+            output.lock();
+            reduction.lock();
+            compression.run_metered(&burst, &mut output, &mut reduction, 4800);
+            reduction.iter().fold(0.0f64, |deepest, &reduction| deepest.min(reduction))
+        };
+        assert!(deepest(DetectionMode::Peak) < -10.0);
+        assert!(deepest(DetectionMode::Rms) > deepest(DetectionMode::Peak) + 5.0);
+
+        let main = buffer(&(0..4800).map(|index| 0.1 * (index as f64 * 0.05).sin()).collect::<Vec<_>>());
+        let sidechain = buffer(&(0..4800).map(|index| (index as f64 * 0.01).sin()).collect::<Vec<_>>());
+        let level = |output : &Buffer<f64>| output.iter().skip(2400).fold(0.0f64, |peak, data| peak.max(data.abs()));
+        let mut compression = Compression::<f64> { threshold : -10.0, ratio : 10.0, attack : 1.0, release : 100.0, ..Compression::new(48000.0) };
+        let mut output = Buffer::new(4800);
+        output.lock();
+        compression.run(&main, &mut output, 4800);
+        assert!((level(&output) - 0.1).abs() < 1e-3);
+        let mut compression = Compression::<f64> { threshold : -10.0, ratio : 10.0, attack : 1.0, release : 100.0, ..Compression::new(48000.0) };
+        compression.run_sidechain(&main, &sidechain, &mut output, 4800);
+        assert!(level(&output) < 0.05);
+    }
+
+    #[test]
+    fn reported_reduction_matches_applied_attenuation()
+    {
+        let input = buffer(&(0..4800).map(|index| 0.9 * (index as f64 * 0.05).sin()).collect::<Vec<_>>());
+        let (mut output, mut reduction) = (Buffer::new(4800), Buffer::new(4800));
+        output.lock();
+        reduction.lock();
+        let mut compression = Compression::<f64> { threshold : -12.0, ratio : 4.0, attack : 1.0, release : 50.0, makeup : 3.0, ..Compression::new(48000.0) };
+        compression.run_metered(&input, &mut output, &mut reduction, 4800);
+        for index in (0..4800).filter(|&index| input[index].abs() > 1e-3) { assert!((ratio_to_db(output[index] / input[index]) - (reduction[index] + 3.0)).abs() < 1e-9); }
+        assert!(reduction[4799] < -3.0 && compression.last_gain_reduction_db() == reduction[4799]);
+
+        let mut limit = Limit::<f64> { ceiling : -6.0, release : 50.0, ..Limit::new(48000.0) };
+        limit.run_metered(&input, &mut output, &mut reduction, 4800);
+        for index in (0..4800).filter(|&index| input[index].abs() > 1e-3) { assert!((ratio_to_db(output[index] / input[index]) - reduction[index]).abs() < 1e-9); }
+        assert!(output.iter().all(|data| data.abs() <= db_to_ratio(-6.0) + 1e-12));
+        assert!(limit.last_gain_reduction_db() == reduction[4799]);
+    }
+
+    #[test]
+    fn lookahead_ramps_reduction_in_before_the_peak()
+    {
+        let impulse = buffer(&(0..2000).map(|index| if index == 1000 { 1.0 } else { 0.0 }).collect::<Vec<_>>());
+        let limited = |lookahead : f64|
+        {
+            let mut limit = Limit::<f64> { ceiling : -6.0, release : 50.0, lookahead, ..Limit::new(48000.0) };
+            let (mut output, mut reduction) = (Buffer::new(2000), Buffer::new(2000));
+            output.lock();
+            reduction.lock();
+            limit.run_metered(&impulse, &mut output, &mut reduction, 2000);
+            let steepest = (1..2000).fold(0.0f64, |steepest, index| steepest.max(reduction[index - 1] - reduction[index]));
+            (output.to_vec(), steepest, limit.latency_samples())
+        };
+        let (output, steepest, latency) = limited(1.0);
+        assert_eq!(latency, 48);
+        assert!(output.iter().all(|data| data.abs() <= db_to_ratio(-6.0) + 1e-12));
+        assert!((output[1048] - db_to_ratio(-6.0)).abs() < 1e-9);
+        assert!(steepest < 6.0 / 40.0);
+        // Without lookahead, the full reduction lands in one sample on the peak itself.
+        let (output, steepest, latency) = limited(0.0);
+        assert_eq!(latency, 0);
+        assert!((output[1000] - db_to_ratio(-6.0)).abs() < 1e-9);
+        assert!(steepest > 5.99);
+    }
+
+    #[test]
+    fn interpolated_delay_sweeps_without_discontinuities()
+    {
+        // Largest second difference of the wet signal of a 1kHz sine while the time sweeps from 5ms to 15ms.
+        let roughness = |interpolation : DelayInterpolation|
+        {
+            let mut delay = Delay::<f64> { feedback : 0.0, mix : 100.0, interpolation, ..Delay::new(5.0, 48000.0) };
+            delay.set_max_time(20.0);
+            let wet = (0..5760).map(|index|
+            {
+                delay.set_time(5.0 + 10.0 * (index as f64 - 960.0).max(0.0) / 4800.0);
+                let input = (std::f64::consts::TAU * 1000.0 * index as f64 / 48000.0).sin();
+                delay.process(input) - input
+            }).collect::<Vec<_>>();
+            wet.windows(3).skip(960).fold(0.0f64, |roughness, data| roughness.max((data[0] - 2.0 * data[1] + data[2]).abs()))
+        };
+        assert!(roughness(DelayInterpolation::Linear) < 0.05);
+        assert!(roughness(DelayInterpolation::Cubic) < 0.05);
+        assert!(roughness(DelayInterpolation::Integer) > 0.1);
+    }
+
+    #[test]
+    fn chorus_and_flanger_modulate_at_the_rate_and_pass_dry_at_zero_mix()
+    {
+        // Linear interpolation of a ramp is exact, so the fully wet output of input n is n + (n - delay) and gives the delay in samples.
+        let range = |delays : &[f64]| (delays.iter().cloned().fold(f64::MAX, f64::min), delays.iter().cloned().fold(f64::MIN, f64::max));
+        let periods = |delays : &[f64]|
+        {
+            let center = (range(delays).0 + range(delays).1) / 2.0;
+            let crossings = (1..delays.len()).filter(|&index| delays[index - 1] < center && delays[index] >= center).collect::<Vec<_>>();
+            crossings.windows(2).map(|pair| pair[1] - pair[0]).collect::<Vec<_>>()
+        };
+        let mut chorus = Chorus::new(1, 48000.0);
+        (chorus.rate, chorus.mix) = (2.0, 100.0);
+        let delays = (0..96000).map(|index| 2.0 * index as f64 - chorus.process(index as f64)).skip(2000).collect::<Vec<_>>();
+        let (periods_of_chorus, (shortest, longest)) = (periods(&delays), range(&delays));
+        assert!(!periods_of_chorus.is_empty() && periods_of_chorus.iter().all(|&period| period.abs_diff(24000) <= 2));
+        // 15ms with 3ms of depth at 48kHz.
+        assert!((shortest - 576.0).abs() < 0.1 && (longest - 864.0).abs() < 0.1);
+
+        let mut flanger = Flanger::new(48000.0);
+        (flanger.rate, flanger.feedback, flanger.mix) = (1.0, 0.0, 100.0);
+        let delays = (0..144000).map(|index| 2.0 * index as f64 - flanger.process(index as f64)).skip(2000).collect::<Vec<_>>();
+        let periods_of_flanger = periods(&delays);
+        assert!(!periods_of_flanger.is_empty() && periods_of_flanger.iter().all(|&period| period.abs_diff(48000) <= 2));
+
+        let mut chorus = Chorus::new(3, 48000.0);
+        chorus.mix = 0.0;
+        let mut flanger = Flanger::new(48000.0);
+        flanger.mix = 0.0;
+        for index in 0..4800
+        {
+            let input = (index as f64 * 0.07).sin();
+            assert_eq!((chorus.process(input), flanger.process(input)), (input, input));
+        }
+    }
+
+    #[test]
+    fn f32_processors_match_f64()
+    {
+        let input : Vec<f64> = (0..4800).map(|index| 0.9 * (index as f64 * 0.05).sin() * (-(index as f64) / 2000.0).exp()).collect();
+        let mut input_f32 = Buffer::<f32>::new(input.len());
+        input_f32.lock();
+        for (sample, data) in input_f32.iter_mut().zip(&input) { *sample = *data as f32; }
+        input_f32.unlock();
+        let (mut output, mut output_f32) = (Buffer::new(input.len()), Buffer::<f32>::new(input.len()));
+        output.lock();
+        output_f32.lock();
+
+        let mut compression = Compression::<f64> { threshold : -20.0, ratio : 4.0, attack : 5.0, release : 50.0, makeup : 3.0, knee : 6.0, knee_shape : KneeShape::Cubic, ..Compression::new(48000.0) };
+        let mut compression_f32 = Compression::<f32> { threshold : -20.0, ratio : 4.0, attack : 5.0, release : 50.0, makeup : 3.0, knee : 6.0, knee_shape : KneeShape::Cubic, ..Compression::new(48000.0) };
+        compression.run(&buffer(&input), &mut output, input.len());
+        compression_f32.run(&input_f32, &mut output_f32, input.len());
+        assert!(output.iter().zip(output_f32.iter()).all(|(data, data_f32)| (data - *data_f32 as f64).abs() < 1e-5));
+        assert!((compression.last_gain_reduction_db() - compression_f32.last_gain_reduction_db() as f64).abs() < 1e-3);
+
+        for interpolation in [DelayInterpolation::Integer, DelayInterpolation::Linear, DelayInterpolation::Cubic]
+        {
+            let mut delay = Delay::<f64> { interpolation, ..Delay::new(10.26, 48000.0) };
+            let mut delay_f32 = Delay::<f32> { interpolation, ..Delay::new(10.26, 48000.0) };
+            delay.run(&buffer(&input), &mut output, input.len());
+            delay_f32.run(&input_f32, &mut output_f32, input.len());
+            assert!(output.iter().zip(output_f32.iter()).all(|(data, data_f32)| (data - *data_f32 as f64).abs() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn smoothed_value_reaches_target_after_ramp_and_holds()
+    {
+        // 10ms at 48kHz is 480 samples.
+        let mut gain = SmoothedValue::new(0.0, 10.0, 48000.0);
+        gain.set_target(1.0);
+        let ramp : Vec<f64> = (0..480).map(|_| gain.next()).collect();
+        assert!(ramp.windows(2).all(|pair| (pair[1] - pair[0] - 1.0 / 480.0).abs() < 1e-12));
+        assert!((ramp[239] - 0.5).abs() < 1e-12);
+        assert_eq!((ramp[479], gain.is_smoothing()), (1.0, false));
+        assert!((0..100).all(|_| gain.next() == 1.0));
+
+        let mut frequency = SmoothedValue { mode : SmoothingMode::Exponential, ..SmoothedValue::new(100.0, 10.0, 48000.0) };
+        frequency.set_target(1000.0);
+        let mut output = Buffer::new(480);
+        output.lock();
+        frequency.run(&mut output);
+        // Halfway in time is halfway in ratio, the geometric mean.
+        assert!((output[239] - 1000.0f64.sqrt() * 10.0).abs() < 1e-9);
+        assert_eq!((output[478] < 1000.0, output[479], frequency.get_current()), (true, 1000.0, 1000.0));
+
+        // Setting a target mid-ramp ramps from the current value over the full time again.
+        frequency.set_target(500.0);
+        for _ in 0..240 { frequency.next(); }
+        frequency.set_target(1000.0);
+        assert!(frequency.is_smoothing());
+        assert_eq!((0..480).map(|_| frequency.next()).last(), Some(1000.0));
+    }
+
+    #[test]
+    fn gate_passes_loud_burst_and_attenuates_quiet_tail()
+    {
+        // Loud burst at -6dB for 100ms, then a tail at -60dB for 1s, alternating in sign.
+        let input : Vec<f64> = (0..52800).map(|index| if index % 2 == 0 { 1.0 } else { -1.0 } * if index < 4800 { 0.5 } else { 0.001 }).collect();
+        let mut output = Buffer::new(input.len());
+        output.lock();
+        let mut gate = Gate::<f64>::new(48000.0);
+        gate.run(&buffer(&input), &mut output);
+        assert!((0..4800).all(|index| output[index] == input[index]));
+        // Open through the hold time of 10ms, then released towards the range of 60dB with the time constant of 100ms.
+        assert!((4800..5280).all(|index| output[index] == input[index]));
+        let reduction = |index : usize| ratio_to_db(output[index] / input[index]);
+        assert!((reduction(5280 + 4800) + 60.0 * (1.0 - (-1.0f64).exp())).abs() < 0.1);
+        assert!((reduction(52799) + 60.0).abs() < 0.01 && (gate.last_gain_reduction_db() + 60.0).abs() < 0.01);
+
+        // Downward expansion by 2:1 doubles the distance below the threshold of -40dB.
+        let mut expander = Gate::<f64> { ratio : 2.0, ..Gate::new(48000.0) };
+        for data in &input { expander.process(*data); }
+        assert!((expander.last_gain_reduction_db() + 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn stereo_pan_and_mid_side()
+    {
+        let mono = buffer(&[0.5, -1.0, 0.25]);
+        let (left, right) = Stereo::pan(&mono, 0.0);
+        for index in 0..3
+        {
+            assert!((left[index] - right[index]).abs() < 1e-15);
+            assert!((ratio_to_db(left[index] / mono[index]) + 3.0103).abs() < 1e-4);
+        }
+        let (left, right) = Stereo::pan(&mono, -1.0);
+        assert_eq!(left.to_vec(), mono.to_vec());
+        assert!(right.iter().all(|sample| sample.abs() < 1e-16));
+        let (mut left_slice, mut right_slice) = ([0.0; 3], [0.0; 3]);
+        Stereo::pan_slice(&mono, &mut left_slice, &mut right_slice, 1.0);
+        assert!(left_slice.iter().all(|sample| sample.abs() < 1e-16) && right_slice == [0.5, -1.0, 0.25]);
+
+        for (left, right) in [(0.5, 0.5), (0.25, -0.75), (1.0, 0.0), (-0.375, 0.875)]
+        {
+            let (mid, side) = Stereo::encode_ms(left, right);
+            assert_eq!(Stereo::decode_ms(mid, side), (left, right));
+        }
+        let (mut left, mut right) = (buffer(&[1.0, 0.25]), buffer(&[0.0, -0.75]));
+        left.lock();
+        right.lock();
+        Stereo::width(&mut left, &mut right, 0.0);
+        assert_eq!((left.to_vec(), right.to_vec()), (vec![0.5, -0.25], vec![0.5, -0.25]));
+        let (mut left, mut right) = ([1.0, 0.25], [0.0, -0.75]);
+        Stereo::width_slice(&mut left, &mut right, 2.0);
+        assert_eq!((left, right), ([1.5, 0.75], [-0.5, -1.25]));
+    }
+
+    #[test]
+    fn reverb_impulse_tail_is_dense_and_decays()
+    {
+        // Time for the energy of 50ms windows of the impulse response to fall 60dB below the loudest window, and the share of non-zero samples.
+        let decay = |room_size : f64|
+        {
+            let mut reverb = Reverb { room_size, mix : 100.0, ..Reverb::new(44100.0) };
+            let response : Vec<f64> = (0..44100 * 6).map(|index| reverb.process(if index == 0 { 1.0 } else { 0.0 })).collect();
+            let energy : Vec<f64> = response.chunks(2205).map(|window| window.iter().map(|data| data * data).sum::<f64>()).collect();
+            let loudest = energy.iter().copied().fold(0.0, f64::max);
+            let rt60 = energy.iter().rposition(|&energy| 10.0 * (energy / loudest).log10() > -60.0).unwrap() as f64 * 0.05;
+            let dense = response[4410..13230].iter().filter(|data| data.abs() > 0.0).count() as f64 / 8820.0;
+            (rt60, dense)
+        };
+        // Sound loses 20 * log10(feedback) dB on each pass through the combs, of 31ms on average.
+        let expected = |room_size : f64| 60.0 / -ratio_to_db(0.7 + 0.28 * room_size / 100.0) * COMB_TUNING.iter().sum::<usize>() as f64 / 8.0 / 44100.0;
+        for room_size in [20.0, 80.0]
+        {
+            let (rt60, dense) = decay(room_size);
+            assert!((rt60 / expected(room_size) - 1.0).abs() < 0.2, "{rt60} {}", expected(room_size));
+            assert!(dense > 0.99);
+        }
+        assert!(decay(80.0).0 > 2.0 * decay(20.0).0);
+    }
+
+    #[test]
+    fn knee_sweep_has_no_jump_in_gain_or_slope()
+    {
+        // Largest change of gain and of slope between neighbouring points 0.001dB apart, from -30dB to -10dB.
+        let sweep = |knee_shape : KneeShape|
+        {
+            let compression = Compression::<f64> { threshold : -20.0, ratio : 4.0, knee : 10.0, knee_shape, ..Compression::default() };
+            let gains : Vec<f64> = (0..=20000).map(|step| compression.compute_gain(-30.0 + step as f64 * 0.001)).collect();
+            let slopes : Vec<f64> = gains.windows(2).map(|pair| (pair[1] - pair[0]) / 0.001).collect();
+            let jump = |data : &[f64]| data.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f64::max);
+            (jump(&gains), jump(&slopes))
+        };
+        for knee_shape in [KneeShape::Quadratic, KneeShape::Cubic]
+        {
+            // The gain falls at most 0.75dB per dB, and the slope turns by 0.75 over the knee of 10dB, about 1e-4 over a step.
+            let (gain, slope) = sweep(knee_shape);
+            assert!(gain < 0.75 * 0.001 + 1e-9 && slope < 2e-4, "{gain} {slope}");
+        }
+        // A hard knee turns the slope by 0.75 at the threshold, which the sweep detects.
+        assert!(sweep(KneeShape::Hard).1 > 0.5);
+    }
+
+    #[test]
+    fn ping_pong_echoes_alternate_from_the_opposite_channel()
+    {
+        // 10ms at 48kHz is 480 samples between repeats, each halved by the feedback of 50 percent.
+        let mut delay = PingPongDelay { mix : 100.0, ..PingPongDelay::new(10.0, 48000.0) };
+        let mut impulse = vec![0.0; 2400];
+        impulse[0] = 1.0;
+        let (mut left, mut right) = (Buffer::new(2400), Buffer::new(2400));
+        left.lock();
+        right.lock();
+        delay.run_stereo(&buffer(&impulse), &buffer(&[0.0; 2400]), &mut left, &mut right);
+        let echoes = |channel : &Buffer<f64>| channel.iter().enumerate().skip(1).filter(|(_, data)| data.abs() > 1e-12).map(|(index, data)| (index, *data)).collect::<Vec<_>>();
+        assert_eq!(left[0], 1.0);
+        assert_eq!(echoes(&right), vec![(480, 1.0), (1440, 0.25)]);
+        assert_eq!(echoes(&left), vec![(960, 0.5), (1920, 0.125)]);
+    }
+
+
+    #[test]
+    fn two_notes_sum_their_partials()
+    {
+        assert_eq!(midi_note_to_hz(69), 440.0);
+        assert!((midi_note_to_hz(57) - 220.0).abs() < 1e-9 && (midi_note_to_hz(81) - 880.0).abs() < 1e-9);
+
+        // At 40960Hz both notes fall on bins of a 4096 points spectrum, 44 for A4 and 88 for A5.
+        let render = |notes : &[u8]|
+        {
+            let mut oscillator = PolyOscillator { attack : 0.0, ..PolyOscillator::new(4, Waveform::Sine, 40960.0) };
+            for &note in notes { oscillator.note_on(note, 127); }
+            (0..4096).map(|_| oscillator.process()).collect::<Vec<f64>>()
+        };
+        let (chord, low, high) = (render(&[69, 81]), render(&[69]), render(&[81]));
+        for index in 0..chord.len() { assert!((chord[index] - low[index] - high[index]).abs() < 1e-12); }
+        let bins = spectrum(&chord);
+        assert!((bins[44] - 2048.0).abs() < 1.0 && (bins[88] - 2048.0).abs() < 1.0, "{} {}", bins[44], bins[88]);
+        assert!(bins.iter().enumerate().filter(|(bin, _)| *bin != 44 && *bin != 88).all(|(_, &bin)| bin < 1.0));
+    }
+
+
+    #[test]
+    fn adsr_reaches_each_level_in_its_time()
+    {
+        // At 1000Hz each ms is a sample.
+        let mut envelope = Adsr { attack : 10.0, decay : 20.0, sustain : 0.5, release : 50.0, ..Adsr::new(1000.0) };
+        envelope.gate(true);
+        let attack = (0..10).map(|_| envelope.next()).collect::<Vec<f64>>();
+        assert!(attack[8] < 1.0 && attack[9] == 1.0);
+        let decay = (0..20).map(|_| envelope.next()).collect::<Vec<f64>>();
+        assert!(decay[18] > 0.5 && decay[19] == 0.5);
+
+        let mut output = Buffer::new(1000);
+        output.lock();
+        envelope.run(&mut output);
+        assert!(output.iter().all(|&level| level == 0.5));
+        assert_eq!(envelope.get_stage(), AdsrStage::Sustain);
+
+        envelope.gate(false);
+        let release = (0..50).map(|_| envelope.next()).collect::<Vec<f64>>();
+        assert!(release[48] > 0.0 && release[49] == 0.0);
+        assert!(!envelope.is_active());
+
+        // Release from halfway up the attack falls from there in the release time.
+        envelope.gate(true);
+        for _ in 0..5 { envelope.next(); }
+        envelope.gate(false);
+        let release = (0..50).map(|_| envelope.next()).collect::<Vec<f64>>();
+        assert!((release[0] - 0.49).abs() < 1e-12 && release[48] > 0.0 && release[49] == 0.0);
+    }
+
+
+    #[test]
+    fn convolution_reset_drops_the_tail_of_the_previous_signal()
+    {
+        let kernel = [1.0, 0.5, 0.25, 0.125];
+        let (late, early) = (buffer(&[0.0, 0.0, 0.0, 1.0]), buffer(&[1.0, 0.0, 0.0, 0.0]));
+        let mut output = Buffer::new(4);
+        output.lock();
+
+        // Without reset the tail of the late impulse runs into the next block.
+        let mut convolution = Convolution::new(&kernel).unwrap();
+        convolution.run(&late, &mut output);
+        convolution.run(&early, &mut output);
+        assert_eq!(output.to_vec(), [1.5, 0.75, 0.375, 0.125]);
+
+        convolution.run(&late, &mut output);
+        convolution.reset();
+        convolution.run(&early, &mut output);
+        assert_eq!(output.to_vec(), kernel);
+    }
+
+
+    #[test]
+    fn stft_without_changes_reconstructs_the_input()
+    {
+        let data = (0..8192).map(|index| 0.8 * (index as f64 * 0.05).sin() + 0.1 * (index as f64 * 0.71).sin()).collect::<Vec<f64>>();
+        for hop in [256, 512]
+        {
+            let mut stft = Stft::new(1024, hop);
+            let mut output = Vec::new();
+            // Blocks of 300 samples do not line up with the hop.
+            for block in data.chunks(300)
+            {
+                let mut processed = Buffer::new(block.len());
+                processed.lock();
+                stft.process(&buffer(block), &mut processed, |_| {});
+                output.extend_from_slice(&processed);
+            }
+            // Skip the frames that overlap the start, where fewer windows add up.
+            let latency = stft.latency();
+            let error = (2 * latency..data.len()).map(|index| (output[index] - data[index - latency]).abs()).fold(0.0, f64::max);
+            assert!(error < 1e-12, "{hop} {error}");
+        }
+    }
+
+
+    #[test]
+    fn crossover_bands_sum_flat_and_split_at_minus_6db()
+    {
+        for frequencies in [vec![1000.0], vec![200.0, 2000.0]]
+        {
+            let bands = frequencies.len() + 1;
+            for frequency in [30.0f64, 100.0, 200.0, 700.0, 1000.0, 2000.0, 5000.0, 15000.0]
+            {
+                // Whole cycles in the measured 8192 samples, so the phase of the allpass does not move the RMS.
+                let frequency = (frequency * 8192.0 / 48000.0).round() * 48000.0 / 8192.0;
+                let mut crossover = Crossover::new(&frequencies, 48000.0);
+                let sum = magnitude(|data| crossover.process(data).iter().sum(), frequency, 48000.0);
+                assert!(ratio_to_db(sum).abs() < 0.01, "{frequencies:?} {frequency} {sum}");
+            }
+            // The lowest band and the highest band are down 6dB at their crossover frequencies.
+            for (band, frequency) in [(0, frequencies[0]), (bands - 1, frequencies[bands - 2])]
+            {
+                let mut crossover = Crossover::new(&frequencies, 48000.0);
+                let gain = ratio_to_db(magnitude(|data| crossover.process(data)[band], frequency, 48000.0));
+                assert!((gain - -6.02).abs() < 0.1, "{frequencies:?} {band} {gain}");
+            }
+        }
+    }
+
+
+    #[test]
+    fn multiband_compresses_the_low_tone_and_passes_the_high_tone()
+    {
+        let tone = |frequency : f64, index : usize| 0.5 * (std::f64::consts::TAU * frequency * index as f64 / 48000.0).sin();
+        let data = (0..48000).map(|index| tone(100.0, index) + tone(8000.0, index)).collect::<Vec<f64>>();
+        let mut compressor = MultibandCompressor::new(&[1000.0], 48000.0);
+        *compressor.band(0) = Compression { threshold : -30.0, ratio : 10.0, attack : 1.0, release : 50.0, ..Compression::new(48000.0) };
+        let mut output = Vec::new();
+        for block in data.chunks(512)
+        {
+            let mut processed = Buffer::new(block.len());
+            processed.lock();
+            compressor.run(&buffer(block), &mut processed);
+            output.extend_from_slice(&processed);
+        }
+        // Amplitude of the tone over the settled second half, which holds whole cycles of both tones.
+        let amplitude = |frequency : f64|
+        {
+            let bin = (24000..48000).fold(Complex::default(), |bin, index| bin + Complex::from_polar(output[index], -std::f64::consts::TAU * frequency * index as f64 / 48000.0));
+            bin.norm() / 12000.0
+        };
+        let (low, high) = (ratio_to_db(amplitude(100.0) / 0.5), ratio_to_db(amplitude(8000.0) / 0.5));
+        assert!(low < -10.0, "{low}");
+        assert!(high.abs() < 0.05, "{high}");
+    }
+}
@@ -0,0 +1,182 @@
+use std::f64::consts::PI;
+
+/// Window function for FIR design.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Window
+{
+    /// No tapering.
+    Rectangular,
+    /// Raised cosine reaching zero at both ends.
+    Hann,
+    /// Raised cosine with lower first sidelobe.
+    Hamming,
+    /// Three terms cosine window.
+    #[default]
+    Blackman,
+    /// Four terms cosine window with sidelobes below -92dB.
+    BlackmanHarris,
+    /// Kaiser window with the beta, trading main lobe width for sidelobe level.
+    Kaiser(f64)
+}
+impl Window
+{
+    /// Generate the window of the length.
+    pub fn generate(&self, len : usize) -> Vec<f64>
+    {
+        match *self
+        {
+            Window::Rectangular => vec![1.0; len],
+            Window::Hann => hann(len),
+            Window::Hamming => hamming(len),
+            Window::Blackman => blackman(len),
+            Window::BlackmanHarris => blackman_harris(len),
+            Window::Kaiser(beta) => kaiser(len, beta)
+        }
+    }
+}
+
+// Symmetric sum of cosine terms, where the first and last point are the ends of the window.
+#[inline]
+fn cosine_sum(len : usize, coefficients : &[f64]) -> Vec<f64>
+{
+    if len < 2 { return vec![1.0; len] }
+    (0..len).map(|index|
+    {
+        let phase = 2.0 * PI * index as f64 / (len - 1) as f64;
+        coefficients.iter().enumerate().map(|(order, coefficient)| if order % 2 == 0 { *coefficient } else { -*coefficient } * (phase * order as f64).cos()).sum()
+    }).collect()
+}
+
+/// Hann window of the length.
+pub fn hann(len : usize) -> Vec<f64> { cosine_sum(len, &[0.5, 0.5]) }
+/// Hamming window of the length.
+pub fn hamming(len : usize) -> Vec<f64> { cosine_sum(len, &[0.54, 0.46]) }
+/// Blackman window of the length.
+pub fn blackman(len : usize) -> Vec<f64> { cosine_sum(len, &[0.42, 0.5, 0.08]) }
+/// Blackman-Harris window of the length.
+pub fn blackman_harris(len : usize) -> Vec<f64> { cosine_sum(len, &[0.35875, 0.48829, 0.14128, 0.01168]) }
+/// Kaiser window of the length with the beta.
+pub fn kaiser(len : usize, beta : f64) -> Vec<f64>
+{
+    if len < 2 { return vec![1.0; len] }
+    let denominator = bessel_i0(beta);
+    (0..len).map(|index|
+    {
+        let ratio = 2.0 * index as f64 / (len - 1) as f64 - 1.0;
+        bessel_i0(beta * (1.0 - ratio * ratio).max(0.0).sqrt()) / denominator
+    }).collect()
+}
+
+// Modified Bessel function of the first kind and order zero, by power series.
+#[inline]
+fn bessel_i0(x : f64) -> f64
+{
+    let (mut sum, mut term) = (1.0, 1.0);
+    let half = x / 2.0;
+    for order in 1..64
+    {
+        term *= half / order as f64;
+        sum += term * term;
+        if term * term < sum * 1e-17 { break }
+    }
+    sum
+}
+
+#[inline]
+fn sinc(x : f64) -> f64 { if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) } }
+
+// Windowed sinc lowpass kernel, not normalized.
+#[inline]
+fn windowed_sinc(cutoff : f64, sample_rate : f64, num_taps : usize, window : Window) -> Vec<f64>
+{
+    let frequency = cutoff / sample_rate;
+    let center = (num_taps as f64 - 1.0) / 2.0;
+    window.generate(num_taps).iter().enumerate().map(|(index, gain)| 2.0 * frequency * sinc(2.0 * frequency * (index as f64 - center)) * gain).collect()
+}
+
+// Scale the kernel so that its response at the frequency is unity.
+#[inline]
+fn normalize(mut kernel : Vec<f64>, frequency : f64, sample_rate : f64) -> Vec<f64>
+{
+    let omega = 2.0 * PI * frequency / sample_rate;
+    let (real, imaginary) = kernel.iter().enumerate().fold((0.0, 0.0), |(real, imaginary), (index, tap)| (real + tap * (omega * index as f64).cos(), imaginary - tap * (omega * index as f64).sin()));
+    let gain = (real * real + imaginary * imaginary).sqrt();
+    if gain > 0.0 { kernel.iter_mut().for_each(|tap| *tap /= gain); }
+    kernel
+}
+
+/// Design windowed sinc lowpass FIR kernel with unity gain at DC, usable by Convolution.
+pub fn design_lowpass_fir(cutoff_hz : f64, sample_rate : f64, num_taps : usize, window : Window) -> Vec<f64>
+{
+    normalize(windowed_sinc(cutoff_hz, sample_rate, num_taps, window), 0.0, sample_rate)
+}
+
+/// Design windowed sinc highpass FIR kernel with unity gain at Nyquist by spectral inversion.
+/// Even number of taps has no center tap to invert, so it is rounded up to odd.
+pub fn design_highpass_fir(cutoff_hz : f64, sample_rate : f64, num_taps : usize, window : Window) -> Vec<f64>
+{
+    let num_taps = num_taps | 1;
+    let mut kernel = design_lowpass_fir(cutoff_hz, sample_rate, num_taps, window);
+    kernel.iter_mut().for_each(|tap| *tap = -*tap);
+    kernel[num_taps / 2] += 1.0;
+    normalize(kernel, sample_rate / 2.0, sample_rate)
+}
+
+/// Design windowed sinc bandpass FIR kernel with unity gain at the center of the band.
+pub fn design_bandpass_fir(low_hz : f64, high_hz : f64, sample_rate : f64, num_taps : usize, window : Window) -> Vec<f64>
+{
+    let high = windowed_sinc(high_hz, sample_rate, num_taps, window);
+    let low = windowed_sinc(low_hz, sample_rate, num_taps, window);
+    normalize(high.iter().zip(low.iter()).map(|(high, low)| high - low).collect(), (low_hz + high_hz) / 2.0, sample_rate)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Gain of the kernel at the frequency in dB.
+    fn response_db(kernel : &[f64], frequency : f64, sample_rate : f64) -> f64
+    {
+        let omega = 2.0 * PI * frequency / sample_rate;
+        let (real, imaginary) = kernel.iter().enumerate().fold((0.0, 0.0), |(real, imaginary), (index, tap)| (real + tap * (omega * index as f64).cos(), imaginary - tap * (omega * index as f64).sin()));
+        10.0 * (real * real + imaginary * imaginary).log10()
+    }
+
+    #[test]
+    fn windows_are_symmetric_and_peak_at_the_center()
+    {
+        for window in [Window::Rectangular, Window::Hann, Window::Hamming, Window::Blackman, Window::BlackmanHarris, Window::Kaiser(8.0)]
+        {
+            for len in [2, 63, 64]
+            {
+                let data = window.generate(len);
+                assert_eq!(data.len(), len);
+                assert!((0..len).all(|index| (data[index] - data[len - 1 - index]).abs() < 1e-12), "{window:?}");
+                assert!(data.iter().all(|gain| (-1e-12..=1.0 + 1e-12).contains(gain)));
+            }
+            assert!((window.generate(63)[31] - 1.0).abs() < 1e-12);
+        }
+        assert!(hann(63)[0].abs() < 1e-12 && (hamming(63)[0] - 0.08).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fir_kernels_pass_and_stop_their_bands()
+    {
+        let lowpass = design_lowpass_fir(1000.0, 48000.0, 255, Window::Blackman);
+        assert!((lowpass.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(response_db(&lowpass, 500.0, 48000.0).abs() < 0.01);
+        assert!((response_db(&lowpass, 1000.0, 48000.0) + 6.02).abs() < 0.1);
+        // Blackman stops more than 74dB past the transition band of about 5.5 / 255 of the sample rate.
+        for frequency in [2200.0, 4000.0, 10000.0, 23000.0] { assert!(response_db(&lowpass, frequency, 48000.0) < -74.0, "{frequency}"); }
+
+        let highpass = design_highpass_fir(1000.0, 48000.0, 254, Window::Blackman);
+        assert_eq!(highpass.len(), 255);
+        assert!(response_db(&highpass, 24000.0, 48000.0).abs() < 1e-9 && response_db(&highpass, 4000.0, 48000.0).abs() < 0.01);
+        assert!(highpass.iter().sum::<f64>().abs() < 1e-3 && response_db(&highpass, 100.0, 48000.0) < -74.0);
+
+        let bandpass = design_bandpass_fir(2000.0, 6000.0, 48000.0, 255, Window::Kaiser(8.0));
+        assert!(response_db(&bandpass, 4000.0, 48000.0).abs() < 1e-9);
+        for frequency in [0.0, 500.0, 9000.0, 20000.0] { assert!(response_db(&bandpass, frequency, 48000.0) < -70.0, "{frequency}"); }
+    }
+}
@@ -1,7 +1,7 @@
 #![feature(integer_sign_cast)]
 
 //! Modular audio processing library including MKAU plugin format based on Rust.
-//! buffer : includes buffer, push buffer, and circular buffer.
+//! buffer : includes buffer, push buffer, circular buffer, and lock-free ring buffer.
 //! dsp : includes convolution, saturation, compression, limiter, and delay for audio processing.
 //! processor : includes MKAU plugin format.
 
@@ -9,7 +9,7 @@
 //! The library is offered under GPLv3.0 license for non-commercial use.
 //! If you want to use mkaudiolibrary for closed source project, please email to minjaekim@mkaudio.company for agreement and support.
 
-/// includes push buffer and circular buffer.
+/// includes buffer, push buffer, circular buffer and lock-free ring buffer.
 pub mod buffer;
 /// includes convolution and saturation function for audio processing.
 pub mod dsp;
@@ -11,7 +11,7 @@
 //!     parameters : [(String, f64);1],
 //!     internal_buffer : Buffer<f64>
 //! }
-//! impl Plugin { fn new() -> Self { Self { ui : todo!(), parameters : [(format!("parameter"), 0.5)], internal_buffer : Buffer::new(1024).unwrap() } } }
+//! impl Plugin { fn new() -> Self { Self { ui : todo!(), parameters : [(format!("parameter"), 0.5)], internal_buffer : Buffer::new(1024) } } }
 //! impl Processor for Plugin
 //! {
 //!     fn init(& mut self) { todo!() }
@@ -19,10 +19,12 @@
 //!     fn get_parameter(& self, index: usize) -> f64 { self.parameters[index].1 }
 //!     fn set_parameter(& mut self, index: usize, value: f64) { self.parameters[index].1 = value; }
 //!     fn get_parameter_name(& self, index: usize) -> String { self.parameters[index].0.clone() }
+//!     fn num_parameters(& self) -> usize { self.parameters.len() }
+//!     fn parameter_info(& self, index: usize) -> ParameterInfo { ParameterInfo { min : 0.0, max : 2.0, default : 1.0, unit : format!("x"), ..Default::default() } }
 //!     fn open_window(&self) { todo!() }
 //!     fn close_window(&self) { todo!() }
-//!     fn prepare_to_play(&mut self, buffer_size : usize, sample_rate : usize) { self.internal_buffer.resize(buffer_size).unwrap(); }
-//!     fn run(& self, input: &Buffer<Buffer<f64>>, sidechain_in: &Buffer<Buffer<f64>>, output: &mut Buffer<Buffer<f64>>, sidechain_out: &mut Buffer<Buffer<f64>>)
+//!     fn prepare_to_play(&mut self, buffer_size : usize, sample_rate : usize) { self.internal_buffer.resize(buffer_size); }
+//!     fn run(& mut self, input: &Buffer<Buffer<f64>>, sidechain_in: &Buffer<Buffer<f64>>, output: &mut Buffer<Buffer<f64>>, sidechain_out: &mut Buffer<Buffer<f64>>)
 //!     {
 //!         for channel in 0..input.len() { for sample in 0..input[channel].len() { output[channel][sample] = input[channel][sample] * self.parameters[0].1; } }
 //!     }
@@ -35,13 +37,15 @@ use libloading::{Library, Symbol};
 
 use crate::buffer::Buffer;
 
-/// Declare plugin.
+/// Declare plugin. Optionally declare the unique id and the version as (major, minor, patch), which hosts read without creating the plugin.
 #[macro_export]
 macro_rules! declare_plugin
 {
     ($plugin_type:ty, $constructor:path) =>
     {
+        // The trait object is only passed to load and describe of the same library, never to C.
         #[no_mangle]
+        #[allow(improper_ctypes_definitions)]
         pub extern "C" fn _create() -> * mut dyn Processor
         {
             let constructor : fn() -> $plugin_type = $constructor;
@@ -50,27 +54,83 @@ macro_rules! declare_plugin
             return Box::into_raw(boxed);
         }
     };
+    ($plugin_type:ty, $constructor:path, $unique_id:expr, $version:expr) =>
+    {
+        $crate::declare_plugin!($plugin_type, $constructor);
+        #[no_mangle]
+        pub extern "C" fn _unique_id() -> u32 { $unique_id }
+        #[no_mangle]
+        pub extern "C" fn _version() -> u64
+        {
+            let (major, minor, patch) : (u16, u16, u16) = $version;
+            (major as u64) << 32 | (minor as u64) << 16 | patch as u64
+        }
+    };
+}
+/// Range and display of a parameter, for hosts to build editors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterInfo
+{
+    pub min : f64,              // Lowest value.
+    pub max : f64,              // Highest value.
+    pub default : f64,          // Value when the processor is created or reset.
+    pub unit : String,          // Unit label such as "dB", "ms" or "Hz".
+    pub is_discrete : bool,     // True if the parameter only takes values on the step.
+    pub step : Option<f64>      // Step between values, if any.
+}
+impl Default for ParameterInfo
+{
+    fn default() -> Self { Self { min : 0.0, max : 1.0, default : 0.0, unit : String::new(), is_discrete : false, step : None } }
 }
+
 pub trait Processor
 {
     ///Initialize processor when loaded.
     fn init(& mut self);
     ///Get name.
     fn name(& self) -> String;
+    ///Get the identifier of the plugin, which stays the same when the file is renamed. 0 if not assigned.
+    fn unique_id(& self) -> u32 { 0 }
+    ///Get the version as (major, minor, patch).
+    fn version(& self) -> (u16, u16, u16) { (0, 0, 0) }
     ///Get the value of the parameter of the index.
     fn get_parameter(& self, index : usize) -> f64;
     ///Set the value of the parameter of the index.
     fn set_parameter(& mut self, index : usize, value : f64);
     ///Get the name of the parameter of the index.
     fn get_parameter_name(& self, index : usize) -> String;
+    ///Get the number of parameters.
+    fn num_parameters(& self) -> usize { 0 }
+    ///Get the range and display of the parameter of the index. Continuous 0 to 1 without unit by default.
+    fn parameter_info(& self, _index : usize) -> ParameterInfo { ParameterInfo::default() }
+    ///Save state of the processor. By default, the parameter count as little-endian u64 followed by each parameter as little-endian f64.
+    fn save_state(& self) -> Vec<u8>
+    {
+        let mut data = (self.num_parameters() as u64).to_le_bytes().to_vec();
+        for index in 0..self.num_parameters() { data.extend_from_slice(&self.get_parameter(index).to_le_bytes()); }
+        data
+    }
+    ///Load state saved by save_state. By default, restores the parameters present in both the data and the processor.
+    fn load_state(& mut self, data : &[u8])
+    {
+        let Some(count) = data.get(0..8).map(|count| u64::from_le_bytes(count.try_into().unwrap()) as usize) else { return };
+        for (index, value) in data[8..].chunks_exact(8).take(count.min(self.num_parameters())).enumerate()
+        {
+            self.set_parameter(index, f64::from_le_bytes(value.try_into().unwrap()));
+        }
+    }
     ///Open the view of the processor.
     fn open_window(&self);
     ///Close the view of the processor.
     fn close_window(&self);
-    ///Prepare internal methods for play.
+    ///Prepare internal methods for play. Latency may change here, so hosts read latency_samples after calling this.
     fn prepare_to_play(&mut self, buffer_size : usize, sample_rate : usize);
-    ///Process with the plugin. Optional sidechain I/O. Buffer size of I/O must be same.
-    fn run(& self, input: &Buffer<Buffer<f64>>, sidechain_in : &Buffer<Buffer<f64>>,
+    ///Get the delay of the output in samples, such as from lookahead, for the host to compensate.
+    fn latency_samples(& self) -> usize { 0 }
+    ///Get the number of samples the output keeps ringing after the input stops, such as from reverb or delay.
+    fn tail_samples(& self) -> usize { 0 }
+    ///Process with the plugin. Optional sidechain I/O. Buffer size of I/O must be same. State such as filters and envelopes persists between calls.
+    fn run(& mut self, input: &Buffer<Buffer<f64>>, sidechain_in : &Buffer<Buffer<f64>>,
            output: &mut Buffer<Buffer<f64>>, sidechain_out : &mut Buffer<Buffer<f64>>);
 }
 ///Loads plugin.
@@ -85,4 +145,340 @@ pub fn load(path : &str, name : &str) -> Result<Box<dyn Processor>, libloading::
         plugin.init();
         Ok(plugin)
     }
-}
\ No newline at end of file
+}
+///Plugin found by scan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginDescriptor
+{
+    ///Path of the .mkap file.
+    pub path : String,
+    ///File name without extension, to pass as the name of load.
+    pub id : String,
+    ///Display name returned by the plugin.
+    pub name : String,
+    ///Identifier of the plugin, 0 if not assigned.
+    pub unique_id : u32,
+    ///Version as (major, minor, patch).
+    pub version : (u16, u16, u16)
+}
+
+///Finds .mkap plugins in the directory and reads their names, sorted by id. Plugins failing to load are skipped.
+pub fn scan(path : &str) -> Vec<PluginDescriptor>
+{
+    let entries = match std::fs::read_dir(path)
+    {
+        Ok(entries) => entries,
+        Err(error) => { eprintln!("ERROR: couldn't read directory {} from error : {}", path, error); return vec![] }
+    };
+    let mut descriptors = vec![];
+    for file in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|file| file.extension().is_some_and(|extension| extension == "mkap"))
+    {
+        let Some(file_path) = file.to_str() else { continue };
+        match describe(file_path)
+        {
+            Ok(descriptor) => descriptors.push(descriptor),
+            Err(error) => eprintln!("ERROR: couldn't load plugin {} from error : {}", file_path, error)
+        }
+    }
+    descriptors.sort_by(|a, b| a.id.cmp(&b.id));
+    descriptors
+}
+
+///Reads name, unique id and version of the plugin file. The id and version declared with declare_plugin take precedence over the methods of the plugin.
+pub fn describe(file_path : &str) -> Result<PluginDescriptor, libloading::Error>
+{
+    let id = std::path::Path::new(file_path).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default().to_string();
+    unsafe
+    {
+        let lib = Library::new(file_path)?;
+        let constructor : Symbol<unsafe extern "C" fn() -> * mut dyn Processor> = lib.get(b"_create\0")?;
+        // The plugin is dropped before its library is unloaded.
+        let plugin = Box::from_raw(constructor());
+        let unique_id = match lib.get::<unsafe extern "C" fn() -> u32>(b"_unique_id\0") { Ok(symbol) => symbol(), Err(_) => plugin.unique_id() };
+        let version = match lib.get::<unsafe extern "C" fn() -> u64>(b"_version\0")
+        {
+            Ok(symbol) => { let version = symbol(); ((version >> 32) as u16, (version >> 16) as u16, version as u16) }
+            Err(_) => plugin.version()
+        };
+        Ok(PluginDescriptor { path : file_path.to_string(), id, name : plugin.name(), unique_id, version })
+    }
+}
+
+///Processors run in series, each feeding its output to the input of the next.
+#[derive(Default)]
+pub struct ProcessorChain
+{
+    processors : Vec<Box<dyn Processor>>,
+    scratch : Vec<Buffer<Buffer<f64>>>
+}
+impl ProcessorChain
+{
+    ///New empty chain.
+    pub fn new() -> Self { Self::default() }
+    ///Add the processor at the end of the chain.
+    pub fn push(&mut self, processor : Box<dyn Processor>) { self.processors.push(processor); }
+    ///Get the number of processors.
+    pub fn len(&self) -> usize { self.processors.len() }
+    ///Return true if the chain has no processor.
+    pub fn is_empty(&self) -> bool { self.processors.is_empty() }
+    ///Prepare every processor for play.
+    pub fn prepare_to_play(&mut self, buffer_size : usize, sample_rate : usize)
+    {
+        for processor in &mut self.processors { processor.prepare_to_play(buffer_size, sample_rate); }
+    }
+    ///Get the sum of the latency of every processor in samples.
+    pub fn latency_samples(&self) -> usize { self.processors.iter().map(|processor| processor.latency_samples()).sum() }
+    ///Get the sum of the tail of every processor in samples.
+    pub fn tail_samples(&self) -> usize { self.processors.iter().map(|processor| processor.tail_samples()).sum() }
+    ///Process with every processor in order. Sidechain I/O is shared by every processor. Buffer size of I/O must be same.
+    ///Intermediate buffers are allocated when the channel count or buffer size changes.
+    pub fn run(&mut self, input : &Buffer<Buffer<f64>>, sidechain_in : &Buffer<Buffer<f64>>,
+               output : &mut Buffer<Buffer<f64>>, sidechain_out : &mut Buffer<Buffer<f64>>)
+    {
+        let stages = self.processors.len();
+        if stages == 0 { return }
+        let buffer_size = if input.is_empty() { 0 } else { input[0].len() };
+        if self.scratch.len() != (stages - 1).min(2) || self.scratch.iter().any(|scratch| scratch.len() != input.len() || (!scratch.is_empty() && scratch[0].len() != buffer_size))
+        {
+            self.scratch = (0..(stages - 1).min(2)).map(|_| scratch(input.len(), buffer_size)).collect();
+        }
+        for (stage, processor) in self.processors.iter_mut().enumerate()
+        {
+            // Intermediate output alternates between two scratch buffers, so each stage reads the output of the last one.
+            match (stage, stage + 1 == stages)
+            {
+                (0, true) => processor.run(input, sidechain_in, output, sidechain_out),
+                (0, false) => processor.run(input, sidechain_in, &mut self.scratch[0], sidechain_out),
+                (_, last) =>
+                {
+                    let (first, second) = self.scratch.split_at_mut(1);
+                    let (previous, next) = if stage % 2 == 1 { (&first[0], second.first_mut()) } else { (&second[0], first.first_mut()) };
+                    if last { processor.run(previous, sidechain_in, output, sidechain_out); }
+                    else if let Some(next) = next { processor.run(previous, sidechain_in, next, sidechain_out); }
+                }
+            }
+        }
+    }
+}
+
+///Buffers of channels, locked so processors can write them as output, unlike the unlocked Buffers of AudioFile::to_buffers.
+fn scratch(channels : usize, buffer_size : usize) -> Buffer<Buffer<f64>>
+{
+    let mut buffers = Buffer::channels(channels, buffer_size);
+    buffers.lock();
+    for channel in 0..channels { buffers[channel].lock(); }
+    buffers
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Gain in dB from -60 to 12 on every channel.
+    struct Gain { gain : f64 }
+    impl Processor for Gain
+    {
+        fn init(& mut self) {}
+        fn name(& self) -> String { "Gain".to_string() }
+        fn get_parameter(& self, _index : usize) -> f64 { self.gain }
+        fn set_parameter(& mut self, _index : usize, value : f64) { self.gain = value.clamp(-60.0, 12.0); }
+        fn get_parameter_name(& self, _index : usize) -> String { "Gain".to_string() }
+        fn num_parameters(& self) -> usize { 1 }
+        fn parameter_info(& self, _index : usize) -> ParameterInfo { ParameterInfo { min : -60.0, max : 12.0, default : 0.0, unit : "dB".to_string(), step : Some(0.1), ..Default::default() } }
+        fn open_window(&self) {}
+        fn close_window(&self) {}
+        fn prepare_to_play(&mut self, _buffer_size : usize, _sample_rate : usize) {}
+        fn run(& mut self, input: &Buffer<Buffer<f64>>, _sidechain_in : &Buffer<Buffer<f64>>, output: &mut Buffer<Buffer<f64>>, _sidechain_out : &mut Buffer<Buffer<f64>>)
+        {
+            let gain = crate::dsp::db_to_ratio(self.gain);
+            for channel in 0..input.len() { for index in 0..input[channel].len() { output[channel][index] = input[channel][index] * gain; } }
+        }
+    }
+
+    /// Passes the input through, reporting lookahead of 128 samples once prepared.
+    struct Lookahead { latency : usize }
+    impl Processor for Lookahead
+    {
+        fn init(& mut self) {}
+        fn name(& self) -> String { "Lookahead".to_string() }
+        fn get_parameter(& self, _index : usize) -> f64 { 0.0 }
+        fn set_parameter(& mut self, _index : usize, _value : f64) {}
+        fn get_parameter_name(& self, _index : usize) -> String { String::new() }
+        fn open_window(&self) {}
+        fn close_window(&self) {}
+        fn prepare_to_play(&mut self, _buffer_size : usize, _sample_rate : usize) { self.latency = 128; }
+        fn latency_samples(& self) -> usize { self.latency }
+        fn run(& mut self, input: &Buffer<Buffer<f64>>, _sidechain_in : &Buffer<Buffer<f64>>, output: &mut Buffer<Buffer<f64>>, _sidechain_out : &mut Buffer<Buffer<f64>>)
+        {
+            for channel in 0..input.len() { for index in 0..input[channel].len() { output[channel][index] = input[channel][index]; } }
+        }
+    }
+
+    /// Inverts the polarity of every channel.
+    struct Invert;
+    impl Processor for Invert
+    {
+        fn init(& mut self) {}
+        fn name(& self) -> String { "Invert".to_string() }
+        fn get_parameter(& self, _index : usize) -> f64 { 0.0 }
+        fn set_parameter(& mut self, _index : usize, _value : f64) {}
+        fn get_parameter_name(& self, _index : usize) -> String { String::new() }
+        fn open_window(&self) {}
+        fn close_window(&self) {}
+        fn prepare_to_play(&mut self, _buffer_size : usize, _sample_rate : usize) {}
+        fn run(& mut self, input: &Buffer<Buffer<f64>>, _sidechain_in : &Buffer<Buffer<f64>>, output: &mut Buffer<Buffer<f64>>, _sidechain_out : &mut Buffer<Buffer<f64>>)
+        {
+            for channel in 0..input.len() { for index in 0..input[channel].len() { output[channel][index] = -input[channel][index]; } }
+        }
+    }
+
+    /// One-pole lowpass on every channel, keeping its state between calls of run.
+    struct OnePole { coefficient : f64, state : Vec<f64> }
+    impl Processor for OnePole
+    {
+        fn init(& mut self) {}
+        fn name(& self) -> String { "OnePole".to_string() }
+        fn get_parameter(& self, _index : usize) -> f64 { self.coefficient }
+        fn set_parameter(& mut self, _index : usize, value : f64) { self.coefficient = value; }
+        fn get_parameter_name(& self, _index : usize) -> String { "Coefficient".to_string() }
+        fn open_window(&self) {}
+        fn close_window(&self) {}
+        fn prepare_to_play(&mut self, _buffer_size : usize, _sample_rate : usize) { self.state.clear(); }
+        fn run(& mut self, input: &Buffer<Buffer<f64>>, _sidechain_in : &Buffer<Buffer<f64>>, output: &mut Buffer<Buffer<f64>>, _sidechain_out : &mut Buffer<Buffer<f64>>)
+        {
+            self.state.resize(input.len(), 0.0);
+            for channel in 0..input.len()
+            {
+                for index in 0..input[channel].len()
+                {
+                    self.state[channel] += self.coefficient * (input[channel][index] - self.state[channel]);
+                    output[channel][index] = self.state[channel];
+                }
+            }
+        }
+    }
+
+    /// Locked buffers of the channels, each holding a ramp that starts at the channel index.
+    fn ramps(channels : usize, buffer_size : usize) -> Buffer<Buffer<f64>>
+    {
+        let mut buffers = scratch(channels, buffer_size);
+        for channel in 0..channels { for index in 0..buffer_size { buffers[channel][index] = (channel + index) as f64 * 0.01; } }
+        buffers
+    }
+
+    #[test]
+    fn host_reads_parameter_metadata()
+    {
+        let plugin : Box<dyn Processor> = Box::new(Gain { gain : 0.0 });
+        assert_eq!((plugin.num_parameters(), plugin.get_parameter_name(0)), (1, "Gain".to_string()));
+        let info = plugin.parameter_info(0);
+        assert_eq!((info.min, info.max, info.default, info.unit.as_str(), info.is_discrete, info.step), (-60.0, 12.0, 0.0, "dB", false, Some(0.1)));
+        assert_eq!(plugin.get_parameter(0), info.default);
+    }
+
+    #[test]
+    fn state_restores_parameters()
+    {
+        let mut plugin = Gain { gain : -6.5 };
+        let state = plugin.save_state();
+        assert_eq!(state.len(), 16);
+        assert_eq!((&state[..8], &state[8..]), (&1u64.to_le_bytes()[..], &(-6.5f64).to_le_bytes()[..]));
+        plugin.set_parameter(0, 3.0);
+        plugin.load_state(&state);
+        assert_eq!(plugin.get_parameter(0), -6.5);
+
+        // Truncated or empty state leaves the parameters as they are.
+        plugin.set_parameter(0, 3.0);
+        plugin.load_state(&state[..12]);
+        plugin.load_state(&[]);
+        assert_eq!(plugin.get_parameter(0), 3.0);
+    }
+
+    #[test]
+    fn host_reads_latency_after_prepare_to_play()
+    {
+        let mut plugin : Box<dyn Processor> = Box::new(Lookahead { latency : 0 });
+        plugin.prepare_to_play(512, 48000);
+        assert_eq!((plugin.latency_samples(), plugin.tail_samples()), (128, 0));
+        let plugin : Box<dyn Processor> = Box::new(Gain { gain : 0.0 });
+        assert_eq!((plugin.latency_samples(), plugin.tail_samples()), (0, 0));
+    }
+
+    #[test]
+    fn chain_pipes_each_output_into_the_next_input()
+    {
+        let input = ramps(2, 64);
+        let (sidechain_in, mut sidechain_out) = (scratch(1, 64), scratch(1, 64));
+        let mut output = scratch(2, 64);
+        let mut chain = ProcessorChain::new();
+        assert!(chain.is_empty());
+        chain.push(Box::new(Gain { gain : 6.0 }));
+        chain.push(Box::new(Invert));
+        chain.prepare_to_play(64, 48000);
+        chain.run(&input, &sidechain_in, &mut output, &mut sidechain_out);
+        let gain = crate::dsp::db_to_ratio(6.0);
+        for channel in 0..2 { for index in 0..64 { assert!((output[channel][index] + gain * input[channel][index]).abs() < 1e-12); } }
+
+        // Longer chains alternate between the scratch buffers, and sum the latency of every stage.
+        chain.push(Box::new(Gain { gain : -6.0 }));
+        chain.push(Box::new(Lookahead { latency : 0 }));
+        chain.push(Box::new(Invert));
+        chain.prepare_to_play(64, 48000);
+        chain.run(&input, &sidechain_in, &mut output, &mut sidechain_out);
+        for channel in 0..2 { for index in 0..64 { assert!((output[channel][index] - input[channel][index]).abs() < 1e-12); } }
+        assert_eq!((chain.len(), chain.latency_samples(), chain.tail_samples()), (5, 128, 0));
+    }
+
+    #[test]
+    fn stateful_plugin_filters_across_blocks()
+    {
+        let (sidechain_in, mut sidechain_out) = (scratch(1, 32), scratch(1, 32));
+        let mut whole = OnePole { coefficient : 0.1, state : vec![] };
+        let (input, mut expected) = (ramps(2, 128), scratch(2, 128));
+        whole.run(&input, &scratch(1, 128), &mut expected, &mut scratch(1, 128));
+
+        let mut plugin : Box<dyn Processor> = Box::new(OnePole { coefficient : 0.1, state : vec![] });
+        plugin.prepare_to_play(32, 48000);
+        let (mut block, mut output) = (scratch(2, 32), scratch(2, 32));
+        for start in (0..128).step_by(32)
+        {
+            for channel in 0..2 { for index in 0..32 { block[channel][index] = input[channel][start + index]; } }
+            plugin.run(&block, &sidechain_in, &mut output, &mut sidechain_out);
+            for channel in 0..2 { for index in 0..32 { assert_eq!(output[channel][index], expected[channel][start + index]); } }
+        }
+        // The output lags the ramp of 0.01 per sample by 9 samples once settled, as the one-pole lowpass of 0.1 does.
+        assert!((input[1][127] - output[1][31] - 0.09).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scan_skips_files_that_are_not_plugins()
+    {
+        // Plugins built as separate libraries are out of reach of unit tests, so only the skipping is covered here.
+        let directory = std::env::temp_dir().join(format!("mkaudiolibrary_{}_plugins", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("broken.mkap"), b"not a library").unwrap();
+        std::fs::write(directory.join("readme.txt"), b"").unwrap();
+        std::fs::create_dir_all(directory.join("folder.mkap")).unwrap();
+        let descriptors = scan(directory.to_str().unwrap());
+        assert!(describe(directory.join("broken.mkap").to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(descriptors.is_empty());
+        assert!(scan(directory.to_str().unwrap()).is_empty());
+    }
+
+    fn new_gain() -> Gain { Gain { gain : 0.0 } }
+    crate::declare_plugin!(Gain, new_gain, 0x4D4B4131, (1, 2, 3));
+
+    #[test]
+    fn declared_id_and_version_are_exported()
+    {
+        // describe reads the same symbols from the library and unpacks the version the same way.
+        let version = _version();
+        assert_eq!((_unique_id(), ((version >> 32) as u16, (version >> 16) as u16, version as u16)), (0x4D4B4131, (1, 2, 3)));
+        let plugin = unsafe { Box::from_raw(_create()) };
+        assert_eq!(plugin.name(), "Gain");
+        // Plugins that do not override the methods report no id and version 0.0.0.
+        assert_eq!((plugin.unique_id(), plugin.version()), (0, (0, 0, 0)));
+    }
+}